@@ -0,0 +1,75 @@
+//! Throughput of fetching large streams into a `GreedyAccessReader`.
+//!
+//! Run with `cargo bench`. Each case is timed over a few iterations, and the
+//! best run is reported.
+
+use bra::GreedyAccessReader;
+use std::hint::black_box;
+use std::io::{Read, Result};
+use std::time::{Duration, Instant};
+
+/// The size of each stream.
+const STREAM_SIZE: usize = 64 * 1024 * 1024;
+
+/// The number of times each case is run.
+const ITERATIONS: usize = 5;
+
+/// An endless source which yields at most `chunk` bytes per read, like a
+/// pipe or a socket would.
+struct Chunked {
+    chunk: usize,
+    remaining: usize,
+}
+
+impl Chunked {
+    fn new(chunk: usize) -> Self {
+        Chunked {
+            chunk,
+            remaining: STREAM_SIZE,
+        }
+    }
+}
+
+impl Read for Chunked {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let n = buf.len().min(self.chunk).min(self.remaining);
+        buf[..n].fill(0x5A);
+        self.remaining -= n;
+        Ok(n)
+    }
+}
+
+fn bench<F>(name: &str, mut f: F)
+where
+    F: FnMut(),
+{
+    let mut best = Duration::MAX;
+    for _ in 0..ITERATIONS {
+        let start = Instant::now();
+        f();
+        best = best.min(start.elapsed());
+    }
+    let throughput = STREAM_SIZE as f64 / best.as_secs_f64() / (1024.0 * 1024.0);
+    println!("{:<32} {:>10.2?} {:>10.1} MiB/s", name, best, throughput);
+}
+
+fn main() {
+    for &chunk in &[4 * 1024, 64 * 1024, 1024 * 1024] {
+        bench(&format!("read_all, {} KiB reads", chunk / 1024), || {
+            let mut reader = GreedyAccessReader::new(Chunked::new(chunk));
+            black_box(reader.read_all().unwrap().len());
+        });
+    }
+
+    bench("get at the end, 64 KiB reads", || {
+        let mut reader = GreedyAccessReader::new(Chunked::new(64 * 1024));
+        black_box(reader.get(STREAM_SIZE - 1).unwrap());
+    });
+
+    bench("read_to_end, 64 KiB reads", || {
+        let mut reader = GreedyAccessReader::new(Chunked::new(64 * 1024));
+        let mut out = Vec::with_capacity(STREAM_SIZE);
+        reader.read_to_end(&mut out).unwrap();
+        black_box(out.len());
+    });
+}
@@ -0,0 +1,191 @@
+use crate::error::Result;
+use crate::stable::StableReader;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::io::Read;
+use std::ops::RangeBounds;
+
+/// A buffered random access reader backed by an append-only arena.
+///
+/// Unlike [`GreedyAccessReader`], data is accessed through a shared
+/// reference, and fetched bytes are never moved in memory. Slices obtained
+/// from the reader are therefore tied to the lifetime of the reader rather
+/// than to a single call, so that many of them can be kept alive at once
+/// while more data is fetched. This suits zero-copy parsers holding on to
+/// several borrowed fields.
+///
+/// Data is kept in chunks which double in size, starting at 4 KiB, and is
+/// never copied. A range spanning two chunks cannot be obtained with
+/// [`slice`], which fails with [`Error::Discontiguous`], but only with
+/// [`slice_segments`], as one slice per chunk, or with [`slice_cow`], which
+/// copies it only in that case. For sharing data between threads, see
+/// [`SyncAccessReader`].
+///
+/// [`GreedyAccessReader`]: ./struct.GreedyAccessReader.html
+/// [`slice`]: ./struct.ArenaAccessReader.html#method.slice
+/// [`slice_cow`]: ./struct.ArenaAccessReader.html#method.slice_cow
+/// [`slice_segments`]: ./struct.ArenaAccessReader.html#method.slice_segments
+/// [`Error::Discontiguous`]: ./enum.Error.html#variant.Discontiguous
+/// [`SyncAccessReader`]: ./struct.SyncAccessReader.html
+#[derive(Debug)]
+pub struct ArenaAccessReader<R> {
+    inner: StableReader<RefCell<R>>,
+}
+
+impl<R> ArenaAccessReader<R>
+where
+    R: Read,
+{
+    /// Creates a new arena-backed reader.
+    pub fn new(src: R) -> Self {
+        ArenaAccessReader {
+            inner: StableReader::new(RefCell::new(src)),
+        }
+    }
+
+    /// Drops the buffered data and returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner.into_source()
+    }
+
+    /// Fetches a single byte from the buffered data source.
+    pub fn get(&self, index: usize) -> Result<u8> {
+        self.inner.get(index)
+    }
+
+    /// Obtains a slice of bytes.
+    ///
+    /// If the range's end is not bound (e.g. `5..`), the source is read
+    /// until the end, and the slice contains all remaining data.
+    ///
+    /// # Error
+    ///
+    /// Returns [`Error::OutOfBounds`] if the data source ends before the end
+    /// of the range, [`Error::InvalidRange`] if the range starts after its
+    /// end, [`Error::Discontiguous`] if the range spans more than one chunk,
+    /// or [`Error::Io`] if reading from the source fails.
+    ///
+    /// [`Error::OutOfBounds`]: ./enum.Error.html#variant.OutOfBounds
+    /// [`Error::InvalidRange`]: ./enum.Error.html#variant.InvalidRange
+    /// [`Error::Discontiguous`]: ./enum.Error.html#variant.Discontiguous
+    /// [`Error::Io`]: ./enum.Error.html#variant.Io
+    pub fn slice<T>(&self, range: T) -> Result<&[u8]>
+    where
+        T: RangeBounds<usize>,
+    {
+        self.inner.slice(range)
+    }
+
+    /// Obtains the bytes in the given range, borrowing them if they are held
+    /// in a single chunk, or copying them otherwise.
+    ///
+    /// Unlike [`slice`], this never fails with [`Error::Discontiguous`].
+    ///
+    /// # Error
+    ///
+    /// Fails under the same conditions as [`slice`], except that the range
+    /// may span several chunks.
+    ///
+    /// [`slice`]: ./struct.ArenaAccessReader.html#method.slice
+    /// [`Error::Discontiguous`]: ./enum.Error.html#variant.Discontiguous
+    pub fn slice_cow<T>(&self, range: T) -> Result<Cow<'_, [u8]>>
+    where
+        T: RangeBounds<usize>,
+    {
+        self.inner.slice_cow(range)
+    }
+
+    /// Obtains the bytes in the given range as a sequence of slices, one for
+    /// each chunk spanned by the range, without copying them.
+    ///
+    /// If the range's end is not bound, the source is read until the end.
+    ///
+    /// # Error
+    ///
+    /// Fails under the same conditions as [`slice`], except that the range
+    /// may span several chunks.
+    ///
+    /// [`slice`]: ./struct.ArenaAccessReader.html#method.slice
+    pub fn slice_segments<T>(&self, range: T) -> Result<Vec<&[u8]>>
+    where
+        T: RangeBounds<usize>,
+    {
+        self.inner.slice_segments(range)
+    }
+
+    /// Reads the source until the end, returning the total length of the
+    /// data.
+    pub fn len(&self) -> Result<usize> {
+        self.inner.len()
+    }
+
+    /// Checks whether the data source is empty, fetching at most a few bytes
+    /// from it.
+    pub fn is_empty(&self) -> Result<bool> {
+        self.inner.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ArenaAccessReader;
+    use crate::Error;
+    use std::borrow::Cow;
+
+    #[test]
+    fn smoke_test() {
+        let data: Vec<u8> = (0..=255).collect();
+        let reader = ArenaAccessReader::new(&data[..]);
+
+        assert_eq!(reader.get(10).unwrap(), 10);
+        assert_eq!(reader.slice(250..).unwrap(), &data[250..]);
+        assert_eq!(reader.len().unwrap(), 256);
+        assert!(reader.get(usize::MAX).is_err());
+        assert!(matches!(
+            reader.slice(200..300),
+            Err(Error::OutOfBounds { available: 256, .. })
+        ));
+        assert!(matches!(
+            reader.slice(300..),
+            Err(Error::OutOfBounds { available: 256, .. })
+        ));
+    }
+
+    #[test]
+    fn many_live_slices() {
+        let data: Vec<u8> = (0..50_000u32).map(|i| (i % 251) as u8).collect();
+        let reader = ArenaAccessReader::new(&data[..]);
+
+        let header = reader.slice(..16).unwrap();
+        let fields: Vec<(usize, &[u8])> = (0..data.len())
+            .step_by(997)
+            .filter_map(|i| Some((i, reader.slice(i..i + 300).ok()?)))
+            .collect();
+        let copied: Vec<(usize, Cow<[u8]>)> = (0..data.len() - 300)
+            .step_by(997)
+            .map(|i| (i, reader.slice_cow(i..i + 300).unwrap()))
+            .collect();
+        let body = reader.slice_segments(16..).unwrap();
+
+        assert_eq!(header, &data[..16]);
+        // only the fields spanning chunk boundaries or the end are missing
+        assert_eq!(fields.len(), 49);
+        for (i, field) in fields {
+            assert_eq!(field, &data[i..i + 300]);
+        }
+        assert_eq!(copied.len(), 50);
+        for (i, field) in &copied {
+            assert_eq!(&field[..], &data[*i..*i + 300]);
+        }
+        assert!(matches!(copied[1].1, Cow::Borrowed(_)));
+        assert!(matches!(
+            reader.slice_cow(4000..4200).unwrap(),
+            Cow::Owned(v) if v == data[4000..4200]
+        ));
+        assert_eq!(body.concat(), &data[16..]);
+        assert!(matches!(
+            reader.slice(4000..4200),
+            Err(Error::Discontiguous { range }) if range == (4000..4200)
+        ));
+    }
+}
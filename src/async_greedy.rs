@@ -0,0 +1,356 @@
+use crate::error::{Error, Result};
+use crate::position::{open_end, resolve_range, seek_target};
+use std::future::poll_fn;
+use std::io::{Result as IoResult, SeekFrom};
+use std::ops::RangeBounds;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncBufRead, AsyncRead, AsyncSeek, ReadBuf};
+
+/// The minimum number of bytes requested from the source on each fetch.
+const MIN_FETCH: usize = 16;
+
+/// An asynchronous buffered reader that greedily retains all memory read
+/// into a buffer.
+///
+/// This is the asynchronous counterpart of [`GreedyAccessReader`], wrapping
+/// a Tokio [`AsyncRead`] source. Bytes and slices at arbitrary positions are
+/// obtained with [`get`] and [`slice`], which fetch as much data as needed
+/// from the source. The position indices are always relative to the position
+/// of the data source when it was passed to this construct.
+///
+/// Available with the `tokio` feature.
+///
+/// [`GreedyAccessReader`]: ./struct.GreedyAccessReader.html
+/// [`AsyncRead`]: https://docs.rs/tokio/1/tokio/io/trait.AsyncRead.html
+/// [`get`]: ./struct.AsyncGreedyAccessReader.html#method.get
+/// [`slice`]: ./struct.AsyncGreedyAccessReader.html#method.slice
+#[derive(Debug, Clone)]
+pub struct AsyncGreedyAccessReader<R> {
+    inner: R,
+    /// the buffer, whose length is the number of bytes ever initialised,
+    /// so that they are not zeroed again
+    buf: Vec<u8>,
+    /// the number of bytes at the start of `buf` which hold fetched data
+    filled: usize,
+    consumed: usize,
+    /// the target of a seek started but not yet completed
+    seek: Option<SeekFrom>,
+}
+
+impl<R> AsyncGreedyAccessReader<R>
+where
+    R: AsyncRead + Unpin,
+{
+    /// Creates a new greedy buffered reader with the given byte source.
+    pub fn new(src: R) -> Self {
+        AsyncGreedyAccessReader::with_capacity(src, 0)
+    }
+
+    /// Creates a new greedy buffered reader with the given byte source and
+    /// the specified buffer capacity.
+    pub fn with_capacity(src: R, capacity: usize) -> Self {
+        AsyncGreedyAccessReader {
+            inner: src,
+            buf: Vec::with_capacity(capacity),
+            filled: 0,
+            consumed: 0,
+            seek: None,
+        }
+    }
+
+    /// Retrieves the internal reader, discarding the buffer in the process.
+    ///
+    /// Note that any leftover data in the internal buffer is lost.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Retrieves the internal buffer in its current state, discarding the
+    /// reader in the process.
+    pub fn into_buffer(self) -> Vec<u8> {
+        self.into_parts().1
+    }
+
+    /// Retrieves the internal reader and buffer in their current state.
+    pub fn into_parts(mut self) -> (R, Vec<u8>) {
+        self.buf.truncate(self.filled);
+        (self.inner, self.buf)
+    }
+
+    /// Fetches a single byte from the buffered data source.
+    ///
+    /// # Error
+    ///
+    /// Returns [`Error::OutOfBounds`] if the data source ends before the
+    /// given index, or [`Error::Io`] if reading from the source fails.
+    ///
+    /// [`Error::OutOfBounds`]: ./enum.Error.html#variant.OutOfBounds
+    /// [`Error::Io`]: ./enum.Error.html#variant.Io
+    pub async fn get(&mut self, index: usize) -> Result<u8> {
+        let e = Error::range_end(index, 1, self.filled)?;
+        self.prefetch_up_to(e).await?;
+
+        self.data().get(index).cloned().ok_or(Error::OutOfBounds {
+            range: index..e,
+            available: self.filled,
+        })
+    }
+
+    /// Obtains a slice of bytes.
+    ///
+    /// If the range's end is not bound, the source is read until the end.
+    ///
+    /// # Error
+    ///
+    /// Returns [`Error::OutOfBounds`] if the data source ends before the end
+    /// of the range, [`Error::InvalidRange`] if the range starts after its
+    /// end, or [`Error::Io`] if reading from the source fails.
+    ///
+    /// [`Error::OutOfBounds`]: ./enum.Error.html#variant.OutOfBounds
+    /// [`Error::InvalidRange`]: ./enum.Error.html#variant.InvalidRange
+    /// [`Error::Io`]: ./enum.Error.html#variant.Io
+    pub async fn slice<T>(&mut self, range: T) -> Result<&[u8]>
+    where
+        T: RangeBounds<usize>,
+    {
+        let (b, e) = resolve_range(&range, 0);
+        let e = match e {
+            Some(e) => e,
+            None => {
+                self.prefetch_to_end().await?;
+                open_end(b, self.filled)?
+            }
+        };
+
+        self.prefetch_up_to(e).await?;
+
+        Error::check_range(b, e, self.filled)?;
+        Ok(&self.data()[b..e])
+    }
+
+    /// Reads more data from the source into the buffer, resolving to the
+    /// number of bytes fetched.
+    fn poll_fetch(&mut self, cx: &mut Context) -> Poll<IoResult<usize>> {
+        let b = self.filled;
+        if self.buf.capacity() - b < MIN_FETCH {
+            self.buf.reserve(usize::max(b, MIN_FETCH));
+        }
+        if self.buf.len() < self.buf.capacity() {
+            // only zero the memory which was never initialised before
+            self.buf.resize(self.buf.capacity(), 0);
+        }
+        let mut read_buf = ReadBuf::new(&mut self.buf[b..]);
+        let r = Pin::new(&mut self.inner).poll_read(cx, &mut read_buf);
+        let o = read_buf.filled().len();
+        self.filled += o;
+        r.map_ok(|_| o)
+    }
+
+    fn poll_prefetch_up_to(&mut self, cx: &mut Context, i: usize) -> Poll<IoResult<()>> {
+        while self.filled < i {
+            match self.poll_fetch(cx) {
+                Poll::Ready(Ok(0)) => break,
+                Poll::Ready(Ok(_)) => {}
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_prefetch_to_end(&mut self, cx: &mut Context) -> Poll<IoResult<()>> {
+        loop {
+            match self.poll_fetch(cx) {
+                Poll::Ready(Ok(0)) => return Poll::Ready(Ok(())),
+                Poll::Ready(Ok(_)) => {}
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+
+    async fn prefetch_up_to(&mut self, i: usize) -> IoResult<()> {
+        poll_fn(|cx| self.poll_prefetch_up_to(cx, i)).await
+    }
+
+    async fn prefetch_to_end(&mut self) -> IoResult<()> {
+        poll_fn(|cx| self.poll_prefetch_to_end(cx)).await
+    }
+
+    fn data(&self) -> &[u8] {
+        &self.buf[..self.filled]
+    }
+
+    fn data_to_read(&self) -> &[u8] {
+        self.data().get(self.consumed..).unwrap_or(&[])
+    }
+}
+
+impl<R> AsyncRead for AsyncGreedyAccessReader<R>
+where
+    R: AsyncRead + Unpin,
+{
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context, buf: &mut ReadBuf) -> Poll<IoResult<()>> {
+        let this = self.get_mut();
+        let to_read = match Pin::new(&mut *this).poll_fill_buf(cx) {
+            Poll::Ready(Ok(to_read)) => to_read,
+            Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+            Poll::Pending => return Poll::Pending,
+        };
+
+        let len = usize::min(to_read.len(), buf.remaining());
+        buf.put_slice(&to_read[..len]);
+        this.consumed += len;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<R> AsyncBufRead for AsyncGreedyAccessReader<R>
+where
+    R: AsyncRead + Unpin,
+{
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context) -> Poll<IoResult<&[u8]>> {
+        let this = self.get_mut();
+        if this.data_to_read().is_empty() {
+            let consumed = this.consumed;
+            match this.poll_prefetch_up_to(cx, consumed + 1) {
+                Poll::Ready(Ok(())) => {}
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(Ok(this.data_to_read()))
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        self.get_mut().consumed += amt;
+    }
+}
+
+impl<R> AsyncSeek for AsyncGreedyAccessReader<R>
+where
+    R: AsyncRead + Unpin,
+{
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> IoResult<()> {
+        self.get_mut().seek = Some(position);
+        Ok(())
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, cx: &mut Context) -> Poll<IoResult<u64>> {
+        let this = self.get_mut();
+        let seek = match this.seek {
+            None => return Poll::Ready(Ok(this.consumed as u64)),
+            Some(seek) => seek,
+        };
+        if let SeekFrom::End(_) = seek {
+            match this.poll_prefetch_to_end(cx) {
+                Poll::Ready(Ok(())) => {}
+                Poll::Ready(Err(e)) => {
+                    this.seek = None;
+                    return Poll::Ready(Err(e));
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        let pos = match seek_target(seek, this.consumed, || Ok(this.filled)) {
+            Ok(pos) => pos,
+            Err(e) => {
+                this.seek = None;
+                return Poll::Ready(Err(e));
+            }
+        };
+        // resolve relative seeks only once
+        this.seek = Some(SeekFrom::Start(pos as u64));
+
+        match this.poll_prefetch_up_to(cx, pos) {
+            Poll::Ready(Ok(())) => {
+                this.seek = None;
+                this.consumed = pos;
+                Poll::Ready(Ok(pos as u64))
+            }
+            Poll::Ready(Err(e)) => {
+                this.seek = None;
+                Poll::Ready(Err(e))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AsyncGreedyAccessReader;
+    use crate::Error;
+    use std::io::SeekFrom;
+    use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn smoke_test() {
+        let data = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 50];
+
+        let mut read = AsyncGreedyAccessReader::new(&data[..]);
+        let mut o = Vec::new();
+        read.read_to_end(&mut o).await.unwrap();
+
+        assert_eq!(o, &data);
+    }
+
+    #[tokio::test]
+    #[allow(clippy::reversed_empty_ranges)]
+    async fn test_get_and_slice() {
+        let data = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 50];
+
+        let mut read = AsyncGreedyAccessReader::new(&data[..]);
+
+        assert_eq!(read.get(1).await.unwrap(), 2);
+        assert_eq!(read.get(16).await.unwrap(), 50);
+        assert!(read.get(17).await.is_err());
+        assert!(read.get(usize::MAX).await.is_err());
+        assert_eq!(read.slice(0..0).await.unwrap(), &[]);
+        assert_eq!(read.slice(..=5).await.unwrap(), &[1, 2, 3, 4, 5, 6]);
+        assert_eq!(read.slice(14..).await.unwrap(), &[15, 16, 50]);
+        assert!(read.slice(7..18).await.is_err());
+        assert!(read.slice(6..5).await.is_err());
+        assert!(matches!(
+            read.slice(18..).await,
+            Err(Error::OutOfBounds { available: 17, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_seek() {
+        let data = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 50];
+        let mut read = AsyncGreedyAccessReader::new(&data[..]);
+        let mut chunk = [0; 4];
+
+        assert_eq!(read.seek(SeekFrom::Start(6)).await.unwrap(), 6);
+        read.read_exact(&mut chunk).await.unwrap();
+        assert_eq!(chunk, [7, 8, 9, 10]);
+        assert_eq!(read.seek(SeekFrom::Current(-8)).await.unwrap(), 2);
+        read.read_exact(&mut chunk).await.unwrap();
+        assert_eq!(chunk, [3, 4, 5, 6]);
+        assert_eq!(read.seek(SeekFrom::End(-2)).await.unwrap(), 15);
+        assert_eq!(read.read(&mut chunk).await.unwrap(), 2);
+        assert_eq!(read.get(0).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn pending_reads_keep_data() {
+        let data: Vec<u8> = (0..=255).collect();
+        let sent = data.clone();
+        let (mut tx, rx) = tokio::io::duplex(64);
+        let writer = tokio::spawn(async move {
+            for chunk in sent.chunks(10) {
+                tx.write_all(chunk).await.unwrap();
+                tokio::task::yield_now().await;
+            }
+        });
+
+        let mut read = AsyncGreedyAccessReader::new(rx);
+        assert_eq!(read.get(200).await.unwrap(), 200);
+        writer.await.unwrap();
+        assert_eq!(read.slice(..).await.unwrap(), &data[..]);
+        assert_eq!(read.into_buffer(), data);
+    }
+}
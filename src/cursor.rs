@@ -0,0 +1,233 @@
+use crate::error::Result;
+use crate::greedy::GreedyAccessReader;
+use crate::position::seek_target;
+use std::cell::RefCell;
+use std::io::{BufRead, Read, Result as IoResult, Seek, SeekFrom, Sink, Write};
+use std::rc::Rc;
+
+/// The maximum number of bytes copied from the shared buffer by `fill_buf`.
+const CHUNK_SIZE: usize = 8 * 1024;
+
+/// A reading cursor over a greedy buffer shared with other cursors.
+///
+/// Obtained with [`GreedyAccessReader::into_cursor`], each cursor has its own
+/// reading position, while all of them share the same buffer and data
+/// source. Data is only fetched from the source once, by whichever cursor
+/// needs it first, and remains available to all other cursors. Cloning a
+/// cursor creates another cursor at the same position.
+///
+/// Cursors implement [`Read`], [`BufRead`] and [`Seek`]. Indices are the
+/// same as in the original reader.
+///
+/// [`GreedyAccessReader::into_cursor`]: ./struct.GreedyAccessReader.html#method.into_cursor
+/// [`Read`]: https://doc.rust-lang.org/std/io/trait.Read.html
+/// [`BufRead`]: https://doc.rust-lang.org/std/io/trait.BufRead.html
+/// [`Seek`]: https://doc.rust-lang.org/std/io/trait.Seek.html
+#[derive(Debug)]
+pub struct GreedyCursor<R, W = Sink> {
+    shared: Rc<RefCell<GreedyAccessReader<R, W>>>,
+    /// the index of the next byte to be read
+    pos: usize,
+    /// bytes copied from the shared buffer, only used by `fill_buf` since
+    /// the shared buffer cannot stay borrowed after the call
+    buf: Vec<u8>,
+    /// the index of the first byte in `buf`
+    buf_start: usize,
+}
+
+impl<R, W> GreedyCursor<R, W>
+where
+    R: Read,
+    W: Write,
+{
+    pub(crate) fn new(reader: GreedyAccessReader<R, W>, pos: usize) -> Self {
+        GreedyCursor {
+            shared: Rc::new(RefCell::new(reader)),
+            pos,
+            buf: Vec::new(),
+            buf_start: 0,
+        }
+    }
+
+    /// Obtains the current reading position of this cursor.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Fetches a single byte from the shared buffered data source.
+    ///
+    /// This does not change the reading position of the cursor.
+    pub fn get(&self, index: usize) -> Result<u8> {
+        self.shared.borrow_mut().get(index)
+    }
+
+    /// Retrieves the underlying reader, if this is the only cursor left.
+    /// Otherwise, the cursor is returned back as an error.
+    ///
+    /// The reading position of the reader is left unchanged.
+    pub fn into_reader(self) -> std::result::Result<GreedyAccessReader<R, W>, Self> {
+        let GreedyCursor {
+            shared,
+            pos,
+            buf,
+            buf_start,
+        } = self;
+        Rc::try_unwrap(shared)
+            .map(RefCell::into_inner)
+            .map_err(|shared| GreedyCursor {
+                shared,
+                pos,
+                buf,
+                buf_start,
+            })
+    }
+
+    fn data_to_read(&self) -> &[u8] {
+        self.pos
+            .checked_sub(self.buf_start)
+            .and_then(|i| self.buf.get(i..))
+            .unwrap_or(&[])
+    }
+}
+
+impl<R, W> Clone for GreedyCursor<R, W> {
+    fn clone(&self) -> Self {
+        GreedyCursor {
+            shared: Rc::clone(&self.shared),
+            pos: self.pos,
+            buf: Vec::new(),
+            buf_start: 0,
+        }
+    }
+}
+
+impl<R, W> Read for GreedyCursor<R, W>
+where
+    R: Read,
+    W: Write,
+{
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        let len = {
+            let buffered = self.data_to_read();
+            if buffered.is_empty() {
+                // copy straight from the shared buffer
+                let mut shared = self.shared.borrow_mut();
+                let data = shared.fetch_from(self.pos)?;
+                let len = usize::min(data.len(), buf.len());
+                buf[..len].copy_from_slice(&data[..len]);
+                len
+            } else {
+                let len = usize::min(buffered.len(), buf.len());
+                buf[..len].copy_from_slice(&buffered[..len]);
+                len
+            }
+        };
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl<R, W> BufRead for GreedyCursor<R, W>
+where
+    R: Read,
+    W: Write,
+{
+    fn fill_buf(&mut self) -> IoResult<&[u8]> {
+        if self.data_to_read().is_empty() {
+            let mut shared = self.shared.borrow_mut();
+            let data = shared.fetch_from(self.pos)?;
+            let len = usize::min(data.len(), CHUNK_SIZE);
+            self.buf.clear();
+            self.buf.extend_from_slice(&data[..len]);
+            self.buf_start = self.pos;
+        }
+        Ok(self.data_to_read())
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos += amt;
+    }
+}
+
+impl<R, W> Seek for GreedyCursor<R, W>
+where
+    R: Read,
+    W: Write,
+{
+    fn seek(&mut self, pos: SeekFrom) -> IoResult<u64> {
+        let pos = seek_target(pos, self.pos, || Ok(self.shared.borrow_mut().len()?))?;
+
+        self.pos = pos;
+        Ok(pos as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::Counting;
+    use crate::GreedyAccessReader;
+    use std::cell::Cell;
+    use std::io::{BufRead, Read, Seek, SeekFrom};
+
+    #[test]
+    fn independent_cursors() {
+        let data: Vec<u8> = (0..=255).collect();
+        let count = Cell::new(0);
+        let src = Counting {
+            inner: &data[..],
+            count: &count,
+        };
+
+        let mut header = GreedyAccessReader::new(src).into_cursor();
+        let mut payload = header.clone();
+        payload.seek(SeekFrom::Start(16)).unwrap();
+
+        let mut chunk = [0; 4];
+        header.read_exact(&mut chunk).unwrap();
+        assert_eq!(chunk, [0, 1, 2, 3]);
+        payload.read_exact(&mut chunk).unwrap();
+        assert_eq!(chunk, [16, 17, 18, 19]);
+        header.read_exact(&mut chunk).unwrap();
+        assert_eq!(chunk, [4, 5, 6, 7]);
+
+        let mut rest = Vec::new();
+        payload.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, &data[20..]);
+
+        let mut other = header.clone();
+        assert_eq!(other.position(), 8);
+        other.read_exact(&mut chunk).unwrap();
+        assert_eq!(chunk, [8, 9, 10, 11]);
+        assert_eq!(header.get(255).unwrap(), 255);
+
+        // all data was fetched exactly once
+        assert_eq!(count.get(), 256);
+    }
+
+    #[test]
+    fn reads_from_shared_buffer() {
+        let data: Vec<u8> = (0..20_000u32).map(|i| i as u8).collect();
+        let mut cursor = GreedyAccessReader::new(&data[..]).into_cursor();
+        cursor.get(data.len() - 1).unwrap();
+
+        let mut out = vec![0; data.len()];
+        assert_eq!(cursor.read(&mut out).unwrap(), data.len());
+        assert_eq!(out, data);
+        assert!(cursor.buf.is_empty());
+
+        cursor.seek(SeekFrom::Start(100)).unwrap();
+        assert_eq!(&cursor.fill_buf().unwrap()[..4], &data[100..104]);
+    }
+
+    #[test]
+    fn into_reader() {
+        let data: Vec<u8> = (0..=255).collect();
+        let cursor = GreedyAccessReader::new(&data[..]).into_cursor();
+        let other = cursor.clone();
+
+        let cursor = cursor.into_reader().unwrap_err();
+        drop(other);
+        let mut reader = cursor.into_reader().unwrap();
+        assert_eq!(reader.get(10).unwrap(), 10);
+    }
+}
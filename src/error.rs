@@ -0,0 +1,212 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::io::{Error as IoError, ErrorKind as IoErrorKind};
+use std::ops::Range;
+
+/// The error type for accessing buffered data at arbitrary positions.
+///
+/// It distinguishes access outside of the data from failures of the
+/// underlying source. Any of these can be converted into an I/O error, so
+/// that the `?` operator works in functions returning [`std::io::Result`].
+/// The original error can then be recovered with [`get_ref`] and
+/// [`downcast_ref`].
+///
+/// [`std::io::Result`]: https://doc.rust-lang.org/std/io/type.Result.html
+/// [`get_ref`]: https://doc.rust-lang.org/std/io/struct.Error.html#method.get_ref
+/// [`downcast_ref`]: https://doc.rust-lang.org/std/error/trait.Error.html#method.downcast_ref
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The data source ended before the end of the requested range.
+    ///
+    /// Converts into an I/O error of kind `UnexpectedEof`.
+    OutOfBounds {
+        /// The range which was requested.
+        range: Range<usize>,
+        /// The number of bytes available in the data source.
+        available: usize,
+    },
+    /// The requested range starts after its end.
+    ///
+    /// Converts into an I/O error of kind `InvalidInput`.
+    InvalidRange {
+        /// The range which was requested.
+        range: Range<usize>,
+    },
+    /// The requested data is no longer retained.
+    ///
+    /// Converts into an I/O error of kind `InvalidInput`.
+    Evicted {
+        /// The index which was requested.
+        index: usize,
+        /// The lowest index which could still be accessed.
+        lowest: usize,
+    },
+    /// The requested range spans separately allocated parts of the buffer,
+    /// so it cannot be obtained as a single slice without copying.
+    ///
+    /// Converts into an I/O error of kind `InvalidInput`.
+    Discontiguous {
+        /// The range which was requested.
+        range: Range<usize>,
+    },
+    /// Forwarding fetched data to the tee sink failed.
+    ///
+    /// The data remains buffered, and forwarding it is attempted again on
+    /// the next fetch. Converts into an I/O error of the same kind as the
+    /// sink's error.
+    Tee {
+        /// The index of the first byte which was not forwarded.
+        index: usize,
+        /// The error returned by the sink.
+        source: IoError,
+    },
+    /// An I/O error occurred while reading from the data source.
+    Io(IoError),
+}
+
+/// Result type for accessing buffered data.
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Checks whether the given range is valid and within the first
+    /// `available` bytes of the data.
+    pub(crate) fn check_range(b: usize, e: usize, available: usize) -> Result<()> {
+        if b > e {
+            Err(Error::InvalidRange { range: b..e })
+        } else if e > available {
+            Err(Error::OutOfBounds {
+                range: b..e,
+                available,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Obtains the end of the range of `len` bytes starting at `index`,
+    /// failing with [`Error::OutOfBounds`] if it does not fit in a `usize`.
+    /// As no data source can reach that far, `available` may be the number
+    /// of bytes known so far.
+    ///
+    /// [`Error::OutOfBounds`]: ./enum.Error.html#variant.OutOfBounds
+    pub(crate) fn range_end(index: usize, len: usize, available: usize) -> Result<usize> {
+        index.checked_add(len).ok_or(Error::OutOfBounds {
+            range: index..usize::MAX,
+            available,
+        })
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::OutOfBounds { range, available } => write!(
+                f,
+                "Range {}..{} is out of bounds (only {} bytes are available)",
+                range.start, range.end, available
+            ),
+            Error::InvalidRange { range } => write!(
+                f,
+                "Invalid range {}..{} (start is past the end)",
+                range.start, range.end
+            ),
+            Error::Evicted { index, lowest } => write!(
+                f,
+                "Index {} is no longer retained (lowest available index is {})",
+                index, lowest
+            ),
+            Error::Discontiguous { range } => write!(
+                f,
+                "Range {}..{} spans more than one part of the buffer",
+                range.start, range.end
+            ),
+            Error::Tee { index, source } => write!(
+                f,
+                "Failed to forward data from index {} to the tee sink: {}",
+                index, source
+            ),
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Tee { source, .. } => Some(source),
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<IoError> for Error {
+    /// Wraps an I/O error, or recovers the original error if it was
+    /// converted from this type.
+    fn from(e: IoError) -> Self {
+        if e.get_ref().is_some_and(|inner| inner.is::<Error>()) {
+            let inner = e.into_inner().unwrap();
+            return *inner.downcast::<Error>().unwrap();
+        }
+        Error::Io(e)
+    }
+}
+
+impl From<Error> for IoError {
+    fn from(e: Error) -> Self {
+        let kind = match e {
+            Error::Io(e) => return e,
+            Error::OutOfBounds { .. } => IoErrorKind::UnexpectedEof,
+            Error::InvalidRange { .. } | Error::Evicted { .. } | Error::Discontiguous { .. } => {
+                IoErrorKind::InvalidInput
+            }
+            Error::Tee { ref source, .. } => source.kind(),
+        };
+        IoError::new(kind, e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Error;
+    use std::io::{Error as IoError, ErrorKind as IoErrorKind};
+
+    #[test]
+    #[allow(clippy::reversed_empty_ranges)]
+    fn into_io_error() {
+        let e: IoError = Error::OutOfBounds {
+            range: 4..12,
+            available: 8,
+        }
+        .into();
+        assert_eq!(e.kind(), IoErrorKind::UnexpectedEof);
+        match e.get_ref().and_then(|e| e.downcast_ref::<Error>()) {
+            Some(Error::OutOfBounds { range, available }) => {
+                assert_eq!(range, &(4..12));
+                assert_eq!(*available, 8);
+            }
+            e => panic!("unexpected error {:?}", e),
+        }
+
+        let e: IoError = Error::InvalidRange { range: 6..5 }.into();
+        assert_eq!(e.kind(), IoErrorKind::InvalidInput);
+
+        let e: IoError = Error::Io(IoError::new(IoErrorKind::BrokenPipe, "pipe")).into();
+        assert_eq!(e.kind(), IoErrorKind::BrokenPipe);
+        assert!(e.get_ref().unwrap().downcast_ref::<Error>().is_none());
+
+        let e: IoError = Error::Evicted {
+            index: 3,
+            lowest: 10,
+        }
+        .into();
+        assert!(matches!(
+            Error::from(e),
+            Error::Evicted {
+                index: 3,
+                lowest: 10
+            }
+        ));
+    }
+}
@@ -1,5 +1,11 @@
-use std::io::{BufRead, Error as IoError, ErrorKind as IoErrorKind, Read, Result as IoResult};
-use std::ops::Bound;
+use crate::cursor::GreedyCursor;
+use crate::error::{Error, Result};
+use crate::position::{open_end, resolve_range, seek_target};
+use std::convert::{TryFrom, TryInto};
+use std::io::{
+    BufRead, Chain, Cursor, Error as IoError, ErrorKind as IoErrorKind, Read, Result as IoResult,
+    Seek, SeekFrom, Sink, Write,
+};
 use std::ops::RangeBounds;
 
 /// A buffered reader that greedily retains all memory read into a buffer.
@@ -12,14 +18,311 @@ use std::ops::RangeBounds;
 /// position of the data source when it was passed to this construct via
 /// [`new`] or [`with_capacity`].
 ///
+/// The reader also implements [`Seek`]. Since all data read is retained,
+/// seeking backwards is free, whereas seeking forward fetches the data up to
+/// the new position. Seeking relative to the end reads the whole source.
+///
+/// How much is read from the source at a time, and how the buffer grows,
+/// can be tuned with a [`GreedyAccessReaderBuilder`].
+///
+/// Optionally, all bytes fetched from the source can also be forwarded to a
+/// [`Write`] sink, such as a cache file or a hasher, with [`with_tee`]. Each
+/// byte is forwarded exactly once and in order, regardless of which method
+/// fetched it. By default, fetched bytes are not forwarded anywhere.
+///
 /// [`std::io::BufReader`]: https://doc.rust-lang.org/std/io/struct.BufReader.html
 /// [`new`]: ./struct.GreedyAccessReader.html#method.new
 /// [`with_capacity`]: ./struct.GreedyAccessReader.html#method.with_capacity
-#[derive(Debug, Clone)]
-pub struct GreedyAccessReader<R> {
+/// [`Seek`]: https://doc.rust-lang.org/std/io/trait.Seek.html
+/// [`GreedyAccessReaderBuilder`]: ./struct.GreedyAccessReaderBuilder.html
+/// [`Write`]: https://doc.rust-lang.org/std/io/trait.Write.html
+/// [`with_tee`]: ./struct.GreedyAccessReader.html#method.with_tee
+#[derive(Debug)]
+pub struct GreedyAccessReader<R, W = Sink> {
     inner: R,
+    /// the sink receiving a copy of all fetched bytes
+    tee: W,
+    /// the index of the first byte not yet forwarded to the tee sink
+    teed: usize,
+    /// the buffer, whose length is the number of bytes ever initialised,
+    /// so that they are not zeroed again
     buf: Vec<u8>,
+    /// the number of bytes at the start of `buf` which hold fetched data
+    filled: usize,
+    /// the number of discarded bytes still at the start of `buf`, which
+    /// are removed lazily
+    start: usize,
+    /// the number of bytes fetched from the source and then discarded
+    base: usize,
     consumed: usize,
+    /// the ids of outstanding checkpoints and the reading positions saved
+    /// by them
+    checkpoints: Vec<(u64, usize)>,
+    /// the id of the next checkpoint
+    next_checkpoint: u64,
+    policy: FetchPolicy,
+    counters: Counters,
+}
+
+impl<R, W> Clone for GreedyAccessReader<R, W>
+where
+    R: Clone,
+    W: Clone,
+{
+    fn clone(&self) -> Self {
+        GreedyAccessReader {
+            inner: self.inner.clone(),
+            tee: self.tee.clone(),
+            teed: self.teed,
+            base: self.base,
+            buf: self.buf[self.start..self.filled].to_vec(),
+            filled: self.filled - self.start,
+            start: 0,
+            consumed: self.consumed,
+            checkpoints: self.checkpoints.clone(),
+            next_checkpoint: self.next_checkpoint,
+            policy: self.policy.clone(),
+            counters: self.counters,
+        }
+    }
+}
+
+/// A snapshot of the activity and memory usage of a
+/// [`GreedyAccessReader`], obtained with [`stats`].
+///
+/// The counters accumulate from the creation of the reader, or from the
+/// last call to [`reset_stats`].
+///
+/// [`GreedyAccessReader`]: ./struct.GreedyAccessReader.html
+/// [`stats`]: ./struct.GreedyAccessReader.html#method.stats
+/// [`reset_stats`]: ./struct.GreedyAccessReader.html#method.reset_stats
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Stats {
+    /// The number of bytes fetched from the source.
+    pub bytes_fetched: u64,
+    /// The number of calls to `read` on the source, including failed ones.
+    pub read_calls: u64,
+    /// The number of calls to `read` on the source which returned no bytes.
+    pub zero_length_reads: u64,
+    /// The current capacity of the buffer, in bytes.
+    pub capacity: usize,
+    /// The number of bytes currently retained in the buffer.
+    pub retained: usize,
+    /// The current reading position.
+    pub consumed: usize,
+    /// The largest capacity which the buffer has had, in bytes.
+    pub peak_capacity: usize,
+}
+
+/// The counters behind a [`Stats`] snapshot.
+///
+/// [`Stats`]: ./struct.Stats.html
+#[derive(Debug, Default, Clone, Copy)]
+struct Counters {
+    bytes_fetched: u64,
+    read_calls: u64,
+    zero_length_reads: u64,
+    peak_capacity: usize,
+}
+
+impl Counters {
+    fn starting_at(capacity: usize) -> Self {
+        Counters {
+            peak_capacity: capacity,
+            ..Counters::default()
+        }
+    }
+}
+
+/// The strategy for growing the buffer of a [`GreedyAccessReader`] when it
+/// runs out of capacity.
+///
+/// [`GreedyAccessReader`]: ./struct.GreedyAccessReader.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Growth {
+    /// Doubles the capacity, starting from 16 bytes, until the required
+    /// size is reached. This is the default.
+    Doubling,
+    /// Grows the capacity to exactly the required size, so that memory use
+    /// follows the amount of data fetched, at the expense of more frequent
+    /// reallocations.
+    Linear,
+    /// Grows the capacity in increments of the given number of bytes.
+    FixedStep(usize),
+}
+
+/// The settings of a greedy reader for fetching data from its source.
+#[derive(Debug, Clone)]
+struct FetchPolicy {
+    min_read: usize,
+    max_read: usize,
+    growth: Growth,
+    read_ahead: usize,
+    stable_offsets: bool,
+}
+
+impl Default for FetchPolicy {
+    fn default() -> Self {
+        FetchPolicy {
+            min_read: 16,
+            max_read: usize::MAX,
+            growth: Growth::Doubling,
+            read_ahead: 0,
+            stable_offsets: false,
+        }
+    }
+}
+
+/// A builder for a [`GreedyAccessReader`] with a custom fetching policy.
+///
+/// Small read chunks and no read-ahead suit sources delivering a few bytes
+/// at a time, such as serial ports, while large chunks and read-ahead reduce
+/// the number of reads from disks.
+///
+/// ```
+/// # use bra::{GreedyAccessReaderBuilder, Growth};
+/// let data: Vec<u8> = (0..=255).collect();
+/// let mut reader = GreedyAccessReaderBuilder::new()
+///     .min_read(64)
+///     .max_read(4096)
+///     .growth(Growth::FixedStep(4096))
+///     .read_ahead(1024)
+///     .build(&data[..]);
+/// assert_eq!(reader.get(100).unwrap(), 100);
+/// ```
+///
+/// [`GreedyAccessReader`]: ./struct.GreedyAccessReader.html
+#[derive(Debug, Clone, Default)]
+pub struct GreedyAccessReaderBuilder {
+    capacity: usize,
+    policy: FetchPolicy,
+}
+
+impl GreedyAccessReaderBuilder {
+    /// Creates a builder with the default settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the initial capacity of the buffer. Defaults to 0.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Sets the minimum number of bytes requested from the source in a
+    /// single read, growing the buffer if needed. Defaults to 16.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is zero.
+    pub fn min_read(mut self, size: usize) -> Self {
+        assert!(size > 0, "minimum read size must not be zero");
+        self.policy.min_read = size;
+        self
+    }
+
+    /// Sets the maximum number of bytes requested from the source in a
+    /// single read. Defaults to no limit, in which case all of the spare
+    /// capacity of the buffer is offered to the source.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is zero.
+    pub fn max_read(mut self, size: usize) -> Self {
+        assert!(size > 0, "maximum read size must not be zero");
+        self.policy.max_read = size;
+        self
+    }
+
+    /// Sets the strategy for growing the buffer. Defaults to
+    /// [`Growth::Doubling`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the strategy is [`Growth::FixedStep`] with a step of zero.
+    ///
+    /// [`Growth::Doubling`]: ./enum.Growth.html#variant.Doubling
+    /// [`Growth::FixedStep`]: ./enum.Growth.html#variant.FixedStep
+    pub fn growth(mut self, growth: Growth) -> Self {
+        assert!(
+            growth != Growth::FixedStep(0),
+            "growth step must not be zero"
+        );
+        self.policy.growth = growth;
+        self
+    }
+
+    /// Sets the number of bytes fetched beyond the requested position
+    /// whenever random access needs more data. Defaults to 0.
+    ///
+    /// Note that reading ahead may block on sources which deliver data only
+    /// as it becomes available.
+    pub fn read_ahead(mut self, amount: usize) -> Self {
+        self.policy.read_ahead = amount;
+        self
+    }
+
+    /// Sets whether offsets stay relative to the start of the stream.
+    /// Defaults to `false`.
+    ///
+    /// Normally, [`clear`] and [`discard_before`] shift all indices, so that
+    /// the first byte retained becomes the byte at index `#0`. With stable
+    /// offsets, they only free memory, and all indices remain the position
+    /// of the byte in the stream. Accessing a discarded offset then fails
+    /// with [`Error::Evicted`].
+    ///
+    /// [`clear`]: ./struct.GreedyAccessReader.html#method.clear
+    /// [`discard_before`]: ./struct.GreedyAccessReader.html#method.discard_before
+    /// [`Error::Evicted`]: ./enum.Error.html#variant.Evicted
+    pub fn stable_offsets(mut self, enabled: bool) -> Self {
+        self.policy.stable_offsets = enabled;
+        self
+    }
+
+    /// Creates the reader with the given byte source.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the minimum read size exceeds the maximum read size.
+    pub fn build<R>(self, src: R) -> GreedyAccessReader<R>
+    where
+        R: Read,
+    {
+        self.build_with_tee(src, std::io::sink())
+    }
+
+    /// Creates the reader with the given byte source, forwarding all fetched
+    /// bytes to the given sink.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the minimum read size exceeds the maximum read size.
+    pub fn build_with_tee<R, W>(self, src: R, tee: W) -> GreedyAccessReader<R, W>
+    where
+        R: Read,
+        W: Write,
+    {
+        assert!(
+            self.policy.min_read <= self.policy.max_read,
+            "minimum read size exceeds maximum read size"
+        );
+        GreedyAccessReader {
+            inner: src,
+            tee,
+            teed: 0,
+            base: 0,
+            start: 0,
+            buf: Vec::with_capacity(self.capacity),
+            consumed: 0,
+            checkpoints: Vec::new(),
+            next_checkpoint: 0,
+            filled: 0,
+            counters: Counters::starting_at(self.capacity),
+            policy: self.policy,
+        }
+    }
 }
 
 impl<R> GreedyAccessReader<R>
@@ -30,8 +333,17 @@ where
     pub fn new(src: R) -> Self {
         GreedyAccessReader {
             inner: src,
+            tee: std::io::sink(),
+            teed: 0,
+            base: 0,
+            start: 0,
             buf: Vec::new(),
             consumed: 0,
+            checkpoints: Vec::new(),
+            next_checkpoint: 0,
+            filled: 0,
+            policy: FetchPolicy::default(),
+            counters: Counters::default(),
         }
     }
 
@@ -43,11 +355,137 @@ where
     pub fn with_capacity(src: R, capacity: usize) -> Self {
         GreedyAccessReader {
             inner: src,
+            tee: std::io::sink(),
+            teed: 0,
+            base: 0,
+            start: 0,
             buf: Vec::with_capacity(capacity),
             consumed: 0,
+            checkpoints: Vec::new(),
+            next_checkpoint: 0,
+            filled: 0,
+            policy: FetchPolicy::default(),
+            counters: Counters::starting_at(capacity),
         }
     }
 
+    /// Rebuilds a reader from a snapshot written by [`write_snapshot`] and a
+    /// fresh byte source.
+    ///
+    /// The reader resumes with the same buffered data, reading position and
+    /// offsets mode as the one which wrote the snapshot, so that no data is
+    /// fetched again. The new source must continue where the old one
+    /// stopped, at the position given by [`source_position`]. Other fetch
+    /// settings are not part of the snapshot, and take their default
+    /// values.
+    ///
+    /// # Error
+    ///
+    /// Fails with an error of kind `InvalidData` if the snapshot is not
+    /// valid, or `UnexpectedEof` if it is truncated.
+    ///
+    /// [`write_snapshot`]: ./struct.GreedyAccessReader.html#method.write_snapshot
+    /// [`source_position`]: ./struct.GreedyAccessReader.html#method.source_position
+    pub fn from_snapshot<S>(mut snapshot: S, src: R) -> IoResult<Self>
+    where
+        S: Read,
+    {
+        let mut header = [0; SNAPSHOT_HEADER_LEN];
+        snapshot.read_exact(&mut header)?;
+        let (magic, fields) = header.split_at(SNAPSHOT_MAGIC.len());
+        if magic != SNAPSHOT_MAGIC {
+            return Err(IoError::new(
+                IoErrorKind::InvalidData,
+                "not a greedy reader snapshot",
+            ));
+        }
+        let mut fields = fields
+            .chunks_exact(8)
+            .map(|f| usize::try_from(u64::from_le_bytes(f.try_into().unwrap())));
+        let mut field = || {
+            fields.next().unwrap().map_err(|_| {
+                IoError::new(IoErrorKind::InvalidData, "snapshot does not fit in memory")
+            })
+        };
+        let (base, consumed, len, flags) = (field()?, field()?, field()?, field()?);
+
+        let mut buf = Vec::new();
+        snapshot.take(len as u64).read_to_end(&mut buf)?;
+        if buf.len() != len {
+            return Err(IoError::new(
+                IoErrorKind::UnexpectedEof,
+                "snapshot data is truncated",
+            ));
+        }
+
+        Ok(GreedyAccessReader {
+            inner: src,
+            tee: std::io::sink(),
+            teed: len,
+            base,
+            start: 0,
+            filled: len,
+            counters: Counters::starting_at(buf.capacity()),
+            buf,
+            consumed,
+            checkpoints: Vec::new(),
+            next_checkpoint: 0,
+            policy: FetchPolicy {
+                stable_offsets: flags & SNAPSHOT_STABLE_OFFSETS != 0,
+                ..FetchPolicy::default()
+            },
+        })
+    }
+}
+
+/// The magic bytes at the start of a greedy reader snapshot, including a
+/// format version.
+const SNAPSHOT_MAGIC: &[u8; 8] = b"BRASNAP2";
+
+/// The length of a snapshot's header: the magic bytes, followed by the
+/// number of discarded bytes, the reading position, the length of the data
+/// and the flags, as little endian 64-bit integers.
+const SNAPSHOT_HEADER_LEN: usize = 8 + 4 * 8;
+
+/// The snapshot flag for a reader with stable offsets.
+const SNAPSHOT_STABLE_OFFSETS: usize = 1;
+
+impl<R, W> GreedyAccessReader<R, W>
+where
+    R: Read,
+    W: Write,
+{
+    /// Creates a new greedy buffered reader with the given byte source,
+    /// which forwards all bytes fetched from the source to the given sink.
+    pub fn with_tee(src: R, tee: W) -> Self {
+        GreedyAccessReader {
+            inner: src,
+            tee,
+            teed: 0,
+            base: 0,
+            start: 0,
+            buf: Vec::new(),
+            consumed: 0,
+            checkpoints: Vec::new(),
+            next_checkpoint: 0,
+            filled: 0,
+            policy: FetchPolicy::default(),
+            counters: Counters::default(),
+        }
+    }
+
+    /// Obtains a reference to the sink receiving the fetched bytes.
+    pub fn tee(&self) -> &W {
+        &self.tee
+    }
+
+    /// Obtains a mutable reference to the sink receiving the fetched bytes.
+    ///
+    /// Writing to the sink directly interleaves with the forwarded data.
+    pub fn tee_mut(&mut self) -> &mut W {
+        &mut self.tee
+    }
+
     /// Retrieves the internal reader, discarding the buffer in the process.
     ///
     /// Note that any leftover data in the internal buffer is lost.
@@ -58,120 +496,639 @@ where
     /// Retrieves the internal buffer in its current state, discarding the
     /// reader in the process.
     pub fn into_buffer(self) -> Vec<u8> {
-        self.buf
+        self.into_parts().1
     }
 
     /// Retrieves the internal reader and buffer in their current state.
-    pub fn into_parts(self) -> (R, Vec<u8>) {
+    pub fn into_parts(mut self) -> (R, Vec<u8>) {
+        self.buf.truncate(self.filled);
+        self.buf.drain(..self.start);
         (self.inner, self.buf)
     }
 
+    /// Turns this reader into a plain reader which yields all retained data
+    /// first, and then the rest of the source.
+    ///
+    /// This allows inspecting the start of a stream before handing all of
+    /// it over to code which expects a reader. Data discarded with
+    /// [`clear`] or [`discard_before`] cannot be replayed, and the tee sink
+    /// is dropped.
+    ///
+    /// [`clear`]: ./struct.GreedyAccessReader.html#method.clear
+    /// [`discard_before`]: ./struct.GreedyAccessReader.html#method.discard_before
+    pub fn into_replay_reader(self) -> Chain<Cursor<Vec<u8>>, R> {
+        let (inner, buf) = self.into_parts();
+        Cursor::new(buf).chain(inner)
+    }
+
+    /// Turns this reader into a plain reader which yields the retained data
+    /// from the current reading position onwards, and then the rest of the
+    /// source.
+    pub fn into_replay_reader_at_position(self) -> Chain<Cursor<Vec<u8>>, R> {
+        let consumed = self.consumed;
+        let (inner, buf) = self.into_parts();
+        let mut replay = Cursor::new(buf);
+        replay.set_position(consumed as u64);
+        replay.chain(inner)
+    }
+
+    /// Obtains the position of the source relative to where it was when the
+    /// reader was created, which is the number of bytes fetched so far.
+    ///
+    /// When resuming from a snapshot, this is where the fresh source must
+    /// continue.
+    pub fn source_position(&self) -> u64 {
+        (self.base + self.data().len()) as u64
+    }
+
+    /// Writes a snapshot of the buffered data and reading position, from
+    /// which the reader can be rebuilt later with [`from_snapshot`].
+    ///
+    /// Outstanding checkpoints and the tee sink are not part of the
+    /// snapshot. When writing it to a file, writing to a temporary file
+    /// first and renaming it afterwards prevents a crash from leaving a
+    /// partial snapshot behind.
+    ///
+    /// [`from_snapshot`]: ./struct.GreedyAccessReader.html#method.from_snapshot
+    pub fn write_snapshot<S>(&self, mut out: S) -> IoResult<()>
+    where
+        S: Write,
+    {
+        let mut header = Vec::with_capacity(SNAPSHOT_HEADER_LEN);
+        header.extend_from_slice(SNAPSHOT_MAGIC);
+        let flags = if self.policy.stable_offsets {
+            SNAPSHOT_STABLE_OFFSETS
+        } else {
+            0
+        };
+        for field in [self.base, self.consumed, self.data().len(), flags] {
+            header.extend_from_slice(&(field as u64).to_le_bytes());
+        }
+        out.write_all(&header)?;
+        out.write_all(self.data())?;
+        out.flush()
+    }
+
+    /// Turns this reader into a cursor, which can be cloned into several
+    /// independent cursors sharing the same buffer and data source.
+    ///
+    /// The cursor starts at the current reading position.
+    pub fn into_cursor(self) -> GreedyCursor<R, W> {
+        let pos = self.origin() + self.consumed;
+        GreedyCursor::new(self, pos)
+    }
+
     /// Fetches a single byte from the buffered data source.
-    pub fn get(&mut self, index: usize) -> IoResult<u8> {
-        if let Some(v) = self.buf.get(index) {
+    ///
+    /// # Error
+    ///
+    /// Returns [`Error::OutOfBounds`] if the data source ends before the
+    /// given index, [`Error::Evicted`] if the byte was discarded with stable
+    /// offsets, or [`Error::Io`] if reading from the source fails.
+    ///
+    /// [`Error::OutOfBounds`]: ./enum.Error.html#variant.OutOfBounds
+    /// [`Error::Evicted`]: ./enum.Error.html#variant.Evicted
+    /// [`Error::Io`]: ./enum.Error.html#variant.Io
+    pub fn get(&mut self, index: usize) -> Result<u8> {
+        let i = self.to_local(index)?;
+        if let Some(v) = self.data().get(i) {
             Ok(*v)
         } else {
-            self.prefetch_up_to(index + 1)?;
+            let e = Error::range_end(index, 1, self.origin() + self.data().len())?;
+            self.prefetch_up_to(i + 1)?;
 
-            self.buf
-                .get(index)
-                .cloned()
-                .ok_or_else(|| IoError::new(IoErrorKind::Other, "Index out of bounds"))
+            self.data().get(i).cloned().ok_or(Error::OutOfBounds {
+                range: index..e,
+                available: self.origin() + self.data().len(),
+            })
         }
     }
 
     /// Obtains a slice of bytes.
     ///
-    /// The range's end must be bound (e.g. `5..` is not supported).
+    /// If the range's end is not bound (e.g. `5..`), the source is read
+    /// until the end, and the slice contains all remaining data.
     ///
     /// # Error
     ///
-    /// Returns an I/O error if the range is out of the boundaries
-    ///
-    /// # Panics
+    /// Returns [`Error::OutOfBounds`] if the data source ends before the end
+    /// of the range, [`Error::InvalidRange`] if the range starts after its
+    /// end, [`Error::Evicted`] if the range starts at a byte discarded with
+    /// stable offsets, or [`Error::Io`] if reading from the source fails.
     ///
-    /// Panics if the range is not end bounded.
-    pub fn slice<T>(&mut self, range: T) -> IoResult<&[u8]>
+    /// [`Error::OutOfBounds`]: ./enum.Error.html#variant.OutOfBounds
+    /// [`Error::InvalidRange`]: ./enum.Error.html#variant.InvalidRange
+    /// [`Error::Evicted`]: ./enum.Error.html#variant.Evicted
+    /// [`Error::Io`]: ./enum.Error.html#variant.Io
+    pub fn slice<T>(&mut self, range: T) -> Result<&[u8]>
     where
         T: Clone,
         T: RangeBounds<usize>,
     {
-        let end = range.end_bound();
-        let e = match end {
-            Bound::Unbounded => {
-                unimplemented!("Unbounded end is currently not supported");
+        let origin = self.origin();
+        let (b, e) = resolve_range(&range, origin);
+        let e = match e {
+            Some(e) => e,
+            None => {
+                self.prefetch_to_end()?;
+                open_end(b, origin + self.data().len())?
             }
-            Bound::Excluded(&e) => e,
-            Bound::Included(&e) => e + 1,
         };
 
-        let b = match range.start_bound() {
-            Bound::Unbounded => 0,
-            Bound::Excluded(&b) | Bound::Included(&b) => b,
-        };
+        if b <= e {
+            self.to_local(b)?;
+            self.prefetch_up_to(e - origin)?;
+        }
 
-        self.prefetch_up_to(e)?;
+        Error::check_range(b, e, origin + self.data().len())?;
+        Ok(&self.data()[b - origin..e - origin])
+    }
 
-        if b > e || e > self.buf.len() {
-            Err(IoError::new(IoErrorKind::Other, "Index out of bounds"))
-        } else {
-            Ok(&self.buf[b..e])
+    /// Reads the source until the end, returning the total length of the
+    /// data.
+    ///
+    /// With stable offsets, this includes discarded data.
+    pub fn len(&mut self) -> Result<usize> {
+        self.prefetch_to_end()?;
+        Ok(self.origin() + self.data().len())
+    }
+
+    /// Checks whether the data source is empty, fetching at most a few bytes
+    /// from it.
+    pub fn is_empty(&mut self) -> Result<bool> {
+        self.prefetch_up_to(1)?;
+        Ok(self.origin() == 0 && self.data().is_empty())
+    }
+
+    /// Searches for the first occurrence of `needle` starting at or after
+    /// index `from`, returning the index where it starts, or `None` if the
+    /// source ends before a match is found.
+    ///
+    /// Data is fetched from the source only as needed, and matches spanning
+    /// separate fetches are found as well.
+    ///
+    /// # Error
+    ///
+    /// Returns [`Error::Evicted`] if `from` was discarded with stable
+    /// offsets, or [`Error::Io`] if reading from the source fails.
+    ///
+    /// [`Error::Evicted`]: ./enum.Error.html#variant.Evicted
+    /// [`Error::Io`]: ./enum.Error.html#variant.Io
+    pub fn find(&mut self, needle: &[u8], from: usize) -> Result<Option<usize>> {
+        let mut searched = self.to_local(from)?;
+        loop {
+            let len = self.data().len();
+            if let Some(haystack) = self.data().get(searched..) {
+                if let Some(i) = find_in(haystack, needle) {
+                    return Ok(Some(self.origin() + searched + i));
+                }
+                // a match may still start in the last few bytes
+                searched = usize::max(searched, (len + 1).saturating_sub(needle.len()));
+            }
+            self.prefetch_up_to(len)?;
+            if self.data().len() == len {
+                return Ok(None);
+            }
         }
     }
 
-    /// Clears all memory of past reads, shrinking or freeing the buffer in the
-    /// process. The reader will behave as if freshly constructed, save for
-    /// already prefetched data, so that no bytes are lost. The following byte
-    /// being read becomes the byte at index `#0`.
+    /// Searches for the first occurrence of `byte` at or after index `from`,
+    /// returning its index, or `None` if the source ends before it.
+    ///
+    /// Data is fetched from the source only as needed.
+    ///
+    /// # Error
+    ///
+    /// Returns [`Error::Evicted`] if `from` was discarded with stable
+    /// offsets, or [`Error::Io`] if reading from the source fails.
+    ///
+    /// [`Error::Evicted`]: ./enum.Error.html#variant.Evicted
+    /// [`Error::Io`]: ./enum.Error.html#variant.Io
+    pub fn find_byte(&mut self, byte: u8, from: usize) -> Result<Option<usize>> {
+        self.find(&[byte], from)
+    }
+
+    /// Obtains the offset of the first byte still retained.
+    ///
+    /// This is always 0, unless the reader has stable offsets, in which case
+    /// it is the number of bytes discarded so far.
+    pub fn base_offset(&self) -> usize {
+        self.origin()
+    }
+
+    /// Reads the source until the end, returning all of the data retained,
+    /// which starts at [`base_offset`].
+    ///
+    /// [`base_offset`]: ./struct.GreedyAccessReader.html#method.base_offset
+    pub fn read_all(&mut self) -> Result<&[u8]> {
+        self.prefetch_to_end()?;
+        Ok(self.data())
+    }
+
+    /// Clears all memory of past reads, shrinking or freeing the buffer in
+    /// the process. The reader will behave as if freshly constructed, save
+    /// for already prefetched data, so that no bytes are lost. The following
+    /// byte being read becomes the byte at index `#0`, unless the reader has
+    /// stable offsets.
+    ///
+    /// If there are outstanding checkpoints, the data from the lowest
+    /// position saved by them onwards is kept instead, so that it is still
+    /// possible to rewind to them. Indices are shifted accordingly.
+    ///
+    /// Shrinking the buffer copies the data retained. To drop past reads
+    /// repeatedly, such as after each record of a stream, prefer
+    /// [`discard_before`], which takes amortised constant time and keeps
+    /// the buffer's capacity for further reads.
+    ///
+    /// [`discard_before`]: ./struct.GreedyAccessReader.html#method.discard_before
     pub fn clear(&mut self) {
-        if self.consumed < self.buf.len() {
-            self.buf = self.buf[self.consumed..].to_vec();
-        } else {
-            self.buf = Vec::new();
+        self.discard(self.consumed);
+        self.shrink_to_fit();
+    }
+
+    /// Discards the data before the given index.
+    ///
+    /// At most the data before the current reading position is discarded,
+    /// and the data from the lowest position saved by an outstanding
+    /// checkpoint onwards is kept.
+    /// Unless the reader has stable offsets, indices are shifted so that the
+    /// first byte retained becomes the byte at index `#0`.
+    ///
+    /// Discarded bytes are only marked as such, and removed from the buffer
+    /// once they outnumber the bytes retained, so that discarding takes
+    /// amortised constant time.
+    pub fn discard_before(&mut self, index: usize) {
+        if let Ok(i) = self.to_local(index) {
+            self.discard(usize::min(i, self.consumed));
+        }
+    }
+
+    /// Discards the retained data before the given position in the buffer,
+    /// except for data still needed by checkpoints or the tee sink.
+    fn discard(&mut self, keep_from: usize) {
+        // checkpoints are not in order of position if the reader seeked
+        // between them
+        let keep_from = self
+            .checkpoints
+            .iter()
+            .map(|&(_, pos)| pos)
+            .min()
+            .map_or(keep_from, |c| usize::min(c, keep_from));
+        // data not yet forwarded to the tee sink is kept as well
+        let keep_from = usize::min(keep_from, self.teed);
+        self.start += keep_from;
+        if self.start >= self.filled - self.start {
+            self.compact();
+        }
+        self.consumed -= keep_from;
+        self.teed -= keep_from;
+        self.base += keep_from;
+        for (_, pos) in &mut self.checkpoints {
+            *pos -= keep_from;
+        }
+    }
+
+    /// Saves the current reading position, so that the reader can go back
+    /// to it later with [`rewind`], or release it with [`commit`].
+    ///
+    /// Checkpoints can be nested. Rewinding to or committing a checkpoint
+    /// also releases all checkpoints created after it.
+    ///
+    /// [`rewind`]: ./struct.GreedyAccessReader.html#method.rewind
+    /// [`commit`]: ./struct.GreedyAccessReader.html#method.commit
+    pub fn checkpoint(&mut self) -> Checkpoint {
+        let id = self.next_checkpoint;
+        self.next_checkpoint += 1;
+        self.checkpoints.push((id, self.consumed));
+        Checkpoint {
+            depth: self.checkpoints.len() - 1,
+            id,
         }
-        self.consumed = 0;
+    }
+
+    /// Restores the reading position saved by the given checkpoint,
+    /// releasing it in the process.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the checkpoint was already released, by rewinding to or
+    /// committing an older checkpoint.
+    pub fn rewind(&mut self, checkpoint: Checkpoint) {
+        let pos = self.release(checkpoint);
+        self.consumed = pos;
+    }
+
+    /// Releases the given checkpoint, keeping the current reading position.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the checkpoint was already released, by rewinding to or
+    /// committing an older checkpoint.
+    pub fn commit(&mut self, checkpoint: Checkpoint) {
+        self.release(checkpoint);
+    }
+
+    /// Releases a checkpoint and all newer ones, returning its position.
+    fn release(&mut self, checkpoint: Checkpoint) -> usize {
+        let pos = match self.checkpoints.get(checkpoint.depth) {
+            Some(&(id, pos)) if id == checkpoint.id => pos,
+            _ => panic!("checkpoint was already released"),
+        };
+        self.checkpoints.truncate(checkpoint.depth);
+        pos
+    }
+
+    /// Obtains a snapshot of the reader's statistics.
+    pub fn stats(&self) -> Stats {
+        Stats {
+            bytes_fetched: self.counters.bytes_fetched,
+            read_calls: self.counters.read_calls,
+            zero_length_reads: self.counters.zero_length_reads,
+            capacity: self.buf.capacity(),
+            retained: self.data().len(),
+            consumed: self.origin() + self.consumed,
+            peak_capacity: self.counters.peak_capacity,
+        }
+    }
+
+    /// Resets the statistics counters to zero, and the peak capacity to the
+    /// current capacity of the buffer.
+    pub fn reset_stats(&mut self) {
+        self.counters = Counters::starting_at(self.buf.capacity());
     }
 
     /// Shrinks the internal buffer to minimal capacity.
     pub fn shrink_to_fit(&mut self) {
-        self.buf.shrink_to_fit()
+        self.compact();
+        self.buf.truncate(self.filled);
+        self.buf.shrink_to_fit();
     }
 
-    fn reserve_up_to(&mut self, index: usize) {
-        let mut new_size = 16;
-        while new_size < index || new_size < self.buf.capacity() {
-            new_size *= 2;
+    /// Removes the discarded bytes from the start of the buffer.
+    fn compact(&mut self) {
+        self.buf.copy_within(self.start..self.filled, 0);
+        self.filled -= self.start;
+        self.start = 0;
+    }
+
+    /// The data retained in the buffer.
+    fn data(&self) -> &[u8] {
+        &self.buf[self.start..self.filled]
+    }
+
+    /// Grows the buffer according to the growth strategy, so that it can
+    /// hold at least `size` bytes.
+    fn reserve_up_to(&mut self, size: usize) {
+        let capacity = self.buf.capacity();
+        if size <= capacity {
+            return;
         }
-        let additional = new_size - self.buf.capacity();
-        if additional > 0 {
-            self.buf.reserve(additional);
+        let new_size = match self.policy.growth {
+            Growth::Doubling => {
+                let mut new_size: usize = 16;
+                while new_size < size {
+                    new_size = new_size.saturating_mul(2);
+                }
+                new_size
+            }
+            Growth::Linear => size,
+            Growth::FixedStep(step) => {
+                let steps = (size - capacity).div_ceil(step);
+                capacity.saturating_add(steps.saturating_mul(step))
+            }
+        };
+        self.buf.reserve_exact(new_size - self.buf.len());
+        self.counters.peak_capacity = usize::max(self.counters.peak_capacity, self.buf.capacity());
+    }
+
+    /// Reads once from the source, appending to the buffer, and returns the
+    /// number of bytes read. `wanted` is the number of bytes which the
+    /// caller would like to obtain.
+    fn fetch(&mut self, wanted: usize) -> IoResult<usize> {
+        // forward data left over from a previously failed attempt first
+        self.forward()?;
+
+        let wanted = wanted.clamp(self.policy.min_read, self.policy.max_read);
+        if self.buf.capacity() - self.filled < self.policy.min_read {
+            // make room by dropping discarded data first, so that it is not
+            // copied along when the buffer grows
+            self.compact();
+            self.reserve_up_to(self.filled.saturating_add(wanted));
+        }
+
+        let b = self.filled;
+
+        let e = b + usize::min(self.buf.capacity() - b, self.policy.max_read);
+        if e > self.buf.len() {
+            // only zero the memory which was never initialised before
+            self.buf.resize(e, 0);
+        }
+        let result = self.inner.read(&mut self.buf[b..e]);
+        self.counters.read_calls += 1;
+        match result {
+            Ok(0) => self.counters.zero_length_reads += 1,
+            Ok(n) => self.counters.bytes_fetched += n as u64,
+            Err(_) => {}
+        }
+
+        // exclude the non-written portion
+        let o = *result.as_ref().unwrap_or(&0);
+        self.filled = b + o;
+        let n = result?;
+        self.forward()?;
+        Ok(n)
+    }
+
+    /// Forwards all fetched bytes which were not forwarded yet to the tee
+    /// sink.
+    fn forward(&mut self) -> IoResult<()> {
+        while self.teed < self.data().len() {
+            let index = self.origin() + self.teed;
+            match self
+                .tee
+                .write(&self.buf[self.start + self.teed..self.filled])
+            {
+                Ok(0) => {
+                    let source =
+                        IoError::new(IoErrorKind::WriteZero, "failed to write to tee sink");
+                    return Err(Error::Tee { index, source }.into());
+                }
+                Ok(n) => self.teed += n,
+                Err(ref e) if e.kind() == IoErrorKind::Interrupted => {}
+                Err(source) => return Err(Error::Tee { index, source }.into()),
+            }
         }
+        Ok(())
     }
 
     fn data_to_read(&self) -> &[u8] {
-        &self.buf[self.consumed..]
+        self.data().get(self.consumed..).unwrap_or(&[])
     }
 
     fn prefetch_up_to(&mut self, i: usize) -> IoResult<()> {
-        self.reserve_up_to(i);
-        let mut l = 0;
-        while self.buf.len() <= i {
-            let b = self.fill_buf()?;
-            if b.len() == l {
+        if self.data().len() > i {
+            return Ok(());
+        }
+        let target = i.saturating_add(self.policy.read_ahead);
+        while self.data().len() <= target {
+            let wanted = target - self.data().len() + 1;
+            if self.fetch(wanted)? == 0 {
                 // no extra data since last call, retreat
                 break;
-            } else {
-                // record length, continue fetching
-                l = b.len();
             }
         }
         Ok(())
     }
+
+    /// Fetches data until the byte at the given index is in memory, unless
+    /// the source ends before it, and returns all data buffered from that
+    /// index onwards.
+    pub(crate) fn fetch_from(&mut self, index: usize) -> IoResult<&[u8]> {
+        let i = self.to_local(index)?;
+        self.prefetch_up_to(i)?;
+        Ok(self.data().get(i..).unwrap_or(&[]))
+    }
+
+    /// Obtains the bytes from `b` to `e` if they are already in memory,
+    /// without fetching anything.
+    pub(crate) fn buffered(&self, b: usize, e: usize) -> Option<&[u8]> {
+        let origin = self.origin();
+        self.data()
+            .get(b.checked_sub(origin)?..e.checked_sub(origin)?)
+    }
+
+    /// The index of the first byte retained.
+    fn origin(&self) -> usize {
+        if self.policy.stable_offsets {
+            self.base
+        } else {
+            0
+        }
+    }
+
+    /// Converts an index into a position in the buffer, failing if the byte
+    /// was discarded.
+    fn to_local(&self, index: usize) -> Result<usize> {
+        let origin = self.origin();
+        index.checked_sub(origin).ok_or(Error::Evicted {
+            index,
+            lowest: origin,
+        })
+    }
+
+    fn prefetch_to_end(&mut self) -> IoResult<()> {
+        loop {
+            let l = self.data().len();
+            self.prefetch_up_to(l + 1)?;
+            if self.data().len() == l {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Fetches `N` bytes starting at the given index.
+    fn get_array<const N: usize>(&mut self, index: usize) -> Result<[u8; N]> {
+        let e = Error::range_end(index, N, self.origin() + self.data().len())?;
+        // the local index is never past the external one
+        let i = self.to_local(index)?;
+        self.prefetch_up_to(i + N)?;
+        match self.data().get(i..i + N) {
+            Some(bytes) => Ok(bytes.try_into().unwrap()),
+            None => Err(Error::OutOfBounds {
+                range: index..e,
+                available: self.origin() + self.data().len(),
+            }),
+        }
+    }
+
+    /// Reads `N` bytes from the current position, only consuming them if
+    /// they are all available.
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let bytes = self.get_array(self.origin() + self.consumed)?;
+        self.consumed += N;
+        Ok(bytes)
+    }
+}
+
+/// Finds the position of the first occurrence of `needle` in `haystack`.
+fn find_in(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    match needle {
+        [] => Some(0),
+        [byte] => haystack.iter().position(|b| b == byte),
+        _ => haystack.windows(needle.len()).position(|w| w == needle),
+    }
 }
 
-impl<R> Read for GreedyAccessReader<R>
+/// A reading position saved by [`GreedyAccessReader::checkpoint`].
+///
+/// [`GreedyAccessReader::checkpoint`]: ./struct.GreedyAccessReader.html#method.checkpoint
+#[derive(Debug, PartialEq, Eq)]
+#[must_use = "checkpoints should be either rewound to or committed"]
+pub struct Checkpoint {
+    /// the index of the saved position in the checkpoint stack
+    depth: usize,
+    /// the unique id of the checkpoint, so that a newer checkpoint at the
+    /// same depth is not mistaken for it
+    id: u64,
+}
+
+macro_rules! typed_accessors {
+    ($($t:ty: $get_le:ident, $get_be:ident, $read_le:ident, $read_be:ident;)*) => {
+        /// # Typed accessors
+        ///
+        /// The `get_*` methods fetch a primitive value starting at an
+        /// arbitrary index, whereas the `read_*` methods read it from the
+        /// current reading position, advancing past it. Both fetch as much
+        /// data from the source as needed, and fail with
+        /// [`Error::OutOfBounds`] if the data ends before the value does. In
+        /// the latter case, nothing is consumed.
+        ///
+        /// [`Error::OutOfBounds`]: ./enum.Error.html#variant.OutOfBounds
+        impl<R, W> GreedyAccessReader<R, W>
+        where
+            R: Read,
+            W: Write,
+        {
+            $(
+                #[doc = concat!("Fetches a little endian `", stringify!($t), "` at the given index.")]
+                pub fn $get_le(&mut self, index: usize) -> Result<$t> {
+                    self.get_array(index).map(<$t>::from_le_bytes)
+                }
+
+                #[doc = concat!("Fetches a big endian `", stringify!($t), "` at the given index.")]
+                pub fn $get_be(&mut self, index: usize) -> Result<$t> {
+                    self.get_array(index).map(<$t>::from_be_bytes)
+                }
+
+                #[doc = concat!("Reads a little endian `", stringify!($t), "` from the current position.")]
+                pub fn $read_le(&mut self) -> Result<$t> {
+                    self.read_array().map(<$t>::from_le_bytes)
+                }
+
+                #[doc = concat!("Reads a big endian `", stringify!($t), "` from the current position.")]
+                pub fn $read_be(&mut self) -> Result<$t> {
+                    self.read_array().map(<$t>::from_be_bytes)
+                }
+            )*
+        }
+    };
+}
+
+typed_accessors! {
+    u16: get_u16_le, get_u16_be, read_u16_le, read_u16_be;
+    i16: get_i16_le, get_i16_be, read_i16_le, read_i16_be;
+    u32: get_u32_le, get_u32_be, read_u32_le, read_u32_be;
+    i32: get_i32_le, get_i32_be, read_i32_le, read_i32_be;
+    u64: get_u64_le, get_u64_be, read_u64_le, read_u64_be;
+    i64: get_i64_le, get_i64_be, read_i64_le, read_i64_be;
+    f32: get_f32_le, get_f32_be, read_f32_le, read_f32_be;
+    f64: get_f64_le, get_f64_be, read_f64_le, read_f64_be;
+}
+
+impl<R, W> Read for GreedyAccessReader<R, W>
 where
     R: Read,
+    W: Write,
 {
     fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
         // we'll be reading from the buffer
@@ -182,30 +1139,22 @@ where
         }
 
         let len = usize::min(to_read.len(), buf.len());
-        buf[..len].copy_from_slice(&self.buf[self.consumed..self.consumed + len]);
+        buf[..len].copy_from_slice(&to_read[..len]);
         self.consume(len);
         Ok(len)
     }
 }
 
-impl<R> BufRead for GreedyAccessReader<R>
+impl<R, W> BufRead for GreedyAccessReader<R, W>
 where
     R: Read,
+    W: Write,
 {
     fn fill_buf(&mut self) -> IoResult<&[u8]> {
-        if self.buf.capacity() == self.consumed {
-            self.reserve_up_to(self.buf.capacity() + 16);
+        if self.data_to_read().is_empty() {
+            self.fetch(self.policy.min_read)?;
         }
-
-        let b = self.buf.len();
-        self.buf.resize(self.buf.capacity(), 0);
-        let buf = &mut self.buf[b..];
-        let o = self.inner.read(buf)?;
-
-        // truncate to exclude non-written portion
-        self.buf.truncate(b + o);
-
-        Ok(&self.buf[self.consumed..])
+        Ok(self.data_to_read())
     }
 
     fn consume(&mut self, amt: usize) {
@@ -213,10 +1162,47 @@ where
     }
 }
 
+impl<R, W> Seek for GreedyAccessReader<R, W>
+where
+    R: Read,
+    W: Write,
+{
+    fn seek(&mut self, pos: SeekFrom) -> IoResult<u64> {
+        let origin = self.origin();
+        let pos = seek_target(pos, origin + self.consumed, || {
+            self.prefetch_to_end()?;
+            Ok(origin + self.data().len())
+        })?;
+
+        let i = self.to_local(pos)?;
+        if i > self.data().len() {
+            self.prefetch_up_to(i)?;
+        }
+        self.consumed = i;
+        Ok(pos as u64)
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::GreedyAccessReader;
-    use std::io::Read;
+    use super::{GreedyAccessReader, GreedyAccessReaderBuilder, Growth};
+    use crate::test_util::Chunks;
+    use crate::Error;
+    use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+
+    /// A reader which records the size of the buffer of each read call.
+    struct Recording<'a> {
+        data: &'a [u8],
+        reads: Vec<usize>,
+    }
+
+    impl Read for Recording<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.reads.push(buf.len());
+            self.data.read(buf)
+        }
+    }
+
     #[test]
     fn smoke_test() {
         let data = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 50];
@@ -242,6 +1228,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::reversed_empty_ranges)]
     fn test_slice() {
         let data = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 50];
 
@@ -254,6 +1241,44 @@ mod tests {
         assert_eq!(read.slice(10..12).unwrap(), &[11, 12]);
         assert!(read.slice(7..18).is_err());
         assert!(read.slice(6..5).is_err());
+        assert!(matches!(
+            read.slice(7..18),
+            Err(Error::OutOfBounds { available: 17, .. })
+        ));
+        assert!(matches!(read.slice(6..5), Err(Error::InvalidRange { .. })));
+        assert_eq!(read.slice(14..).unwrap(), &[15, 16, 50]);
+        assert_eq!(read.slice(..).unwrap(), &data);
+        assert_eq!(read.slice(17..).unwrap(), &[]);
+        // a start past the end is out of bounds whether the end is bound or not
+        assert!(matches!(
+            read.slice(18..20),
+            Err(Error::OutOfBounds { available: 17, .. })
+        ));
+        assert!(matches!(
+            read.slice(18..),
+            Err(Error::OutOfBounds { available: 17, .. })
+        ));
+        let e: std::io::Error = read.slice(18..).unwrap_err().into();
+        assert_eq!(e.kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_read_all() {
+        let data = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 50];
+        let mut read = GreedyAccessReader::new(&data[..]);
+
+        assert_eq!(read.get(2).unwrap(), 3);
+        assert_eq!(read.len().unwrap(), 17);
+        assert_eq!(read.read_all().unwrap(), &data);
+
+        let mut read = GreedyAccessReader::new(&data[..]);
+        assert!(!read.is_empty().unwrap());
+        assert_eq!(read.read_all().unwrap(), &data);
+        assert_eq!(read.len().unwrap(), 17);
+
+        let mut read = GreedyAccessReader::new(&[][..]);
+        assert!(read.is_empty().unwrap());
+        assert_eq!(read.len().unwrap(), 0);
     }
 
     #[test]
@@ -300,4 +1325,490 @@ mod tests {
         assert_eq!(read.get(8).unwrap(), 50);
         assert!(read.get(16).is_err());
     }
+
+    #[test]
+    fn test_seek() {
+        let data = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 50];
+        let mut read = GreedyAccessReader::new(&data[..]);
+        let mut chunk = [0; 4];
+
+        assert_eq!(read.seek(SeekFrom::Start(6)).unwrap(), 6);
+        read.read_exact(&mut chunk).unwrap();
+        assert_eq!(chunk, [7, 8, 9, 10]);
+
+        assert_eq!(read.seek(SeekFrom::Current(-8)).unwrap(), 2);
+        read.read_exact(&mut chunk).unwrap();
+        assert_eq!(chunk, [3, 4, 5, 6]);
+
+        assert_eq!(read.seek(SeekFrom::End(-2)).unwrap(), 15);
+        let mut rest = Vec::new();
+        read.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, [16, 50]);
+
+        assert!(read.seek(SeekFrom::Current(-18)).is_err());
+        assert_eq!(read.seek(SeekFrom::Start(20)).unwrap(), 20);
+        assert_eq!(read.read(&mut chunk).unwrap(), 0);
+        assert_eq!(read.get(0).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_typed_accessors() {
+        let data = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 50];
+        let mut read = GreedyAccessReader::new(&data[..]);
+
+        assert_eq!(read.get_u16_le(0).unwrap(), 0x0201);
+        assert_eq!(read.get_u16_be(0).unwrap(), 0x0102);
+        assert_eq!(read.get_u32_le(13).unwrap(), 0x3210_0f0e);
+        assert_eq!(read.get_i64_be(1).unwrap(), 0x0203_0405_0607_0809);
+        assert_eq!(read.get_f32_be(4).unwrap(), f32::from_bits(0x0506_0708));
+
+        match read.get_u32_le(14) {
+            Err(Error::OutOfBounds { range, available }) => {
+                assert_eq!(range, 14..18);
+                assert_eq!(available, 17);
+            }
+            r => panic!("unexpected result {:?}", r),
+        }
+        assert!(matches!(
+            read.get_u32_le(usize::MAX - 1),
+            Err(Error::OutOfBounds { range, .. }) if range == (usize::MAX - 1..usize::MAX)
+        ));
+        assert!(matches!(
+            read.get(usize::MAX),
+            Err(Error::OutOfBounds { range, .. }) if range == (usize::MAX..usize::MAX)
+        ));
+
+        assert_eq!(read.read_u32_be().unwrap(), 0x0102_0304);
+        assert_eq!(read.read_u64_le().unwrap(), 0x0c0b_0a09_0807_0605);
+        assert!(read.read_u64_le().is_err());
+        assert_eq!(read.read_i16_le().unwrap(), 0x0e0d);
+        assert_eq!(read.read_u16_be().unwrap(), 0x0f10);
+    }
+
+    #[test]
+    fn test_checkpoints() {
+        let data = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 50];
+        let mut read = GreedyAccessReader::new(&data[..]);
+
+        let outer = read.checkpoint();
+        assert_eq!(read.read_u16_be().unwrap(), 0x0102);
+        let inner = read.checkpoint();
+        assert_eq!(read.read_u32_be().unwrap(), 0x0304_0506);
+        read.rewind(inner);
+        assert_eq!(read.read_u16_be().unwrap(), 0x0304);
+        read.rewind(outer);
+        assert_eq!(read.read_u16_be().unwrap(), 0x0102);
+
+        let outer = read.checkpoint();
+        let _inner = read.checkpoint();
+        assert_eq!(read.read_u16_be().unwrap(), 0x0304);
+        read.commit(outer);
+        assert_eq!(read.read_u16_be().unwrap(), 0x0506);
+
+        // clearing keeps the data of outstanding checkpoints
+        let cp = read.checkpoint();
+        read.read_exact(&mut [0; 4]).unwrap();
+        read.clear();
+        assert_eq!(read.get(0).unwrap(), 7);
+        read.rewind(cp);
+        assert_eq!(read.read_u16_be().unwrap(), 0x0708);
+    }
+
+    #[test]
+    fn checkpoints_out_of_order() {
+        let data: Vec<u8> = (0..20).collect();
+        let mut read = GreedyAccessReader::new(&data[..]);
+
+        read.seek(SeekFrom::Start(10)).unwrap();
+        let outer = read.checkpoint();
+        read.seek(SeekFrom::Start(2)).unwrap();
+        let inner = read.checkpoint();
+        read.seek(SeekFrom::Start(10)).unwrap();
+        read.clear();
+        assert_eq!(read.get(0).unwrap(), 2);
+        read.rewind(inner);
+        assert_eq!(read.read_u16_be().unwrap(), 0x0203);
+        read.rewind(outer);
+        assert_eq!(read.read_u16_be().unwrap(), 0x0a0b);
+    }
+
+    #[test]
+    #[should_panic(expected = "checkpoint was already released")]
+    fn rewind_released_checkpoint() {
+        let data = [1, 2, 3, 4];
+        let mut read = GreedyAccessReader::new(&data[..]);
+
+        let outer = read.checkpoint();
+        let inner = read.checkpoint();
+        read.commit(outer);
+        read.rewind(inner);
+    }
+
+    #[test]
+    #[should_panic(expected = "checkpoint was already released")]
+    fn rewind_replaced_checkpoint() {
+        let data = [1, 2, 3, 4, 5, 6];
+        let mut read = GreedyAccessReader::new(&data[..]);
+
+        let outer = read.checkpoint();
+        let inner = read.checkpoint();
+        read.commit(outer);
+        read.seek(SeekFrom::Start(5)).unwrap();
+        let _first = read.checkpoint();
+        let _second = read.checkpoint();
+        read.rewind(inner);
+    }
+
+    #[test]
+    fn test_fetch_policy() {
+        let data: Vec<u8> = (0..=255).collect();
+        let src = Recording {
+            data: &data,
+            reads: Vec::new(),
+        };
+        let mut read = GreedyAccessReaderBuilder::new()
+            .min_read(4)
+            .max_read(8)
+            .growth(Growth::Linear)
+            .build(src);
+
+        assert_eq!(read.get(20).unwrap(), 20);
+        let mut chunk = [0; 30];
+        read.read_exact(&mut chunk).unwrap();
+        assert_eq!(read.slice(..=255).unwrap(), &data[..]);
+        let (src, buf) = read.into_parts();
+        assert!(src.reads.iter().all(|&n| (4..=8).contains(&n)));
+        assert_eq!(buf.len(), 256);
+        assert!(buf.capacity() <= 256 + 8);
+
+        let src = Recording {
+            data: &data,
+            reads: Vec::new(),
+        };
+        let mut read = GreedyAccessReaderBuilder::new()
+            .growth(Growth::FixedStep(100))
+            .read_ahead(50)
+            .build(src);
+
+        assert_eq!(read.get(10).unwrap(), 10);
+        assert_eq!(read.into_buffer().len(), 100);
+    }
+
+    #[test]
+    fn test_partial_reads() {
+        let data: Vec<u8> = (0..1000u32).map(|i| (i % 251) as u8).collect();
+        let mut read = GreedyAccessReader::new(Chunks {
+            data: &data,
+            size: 3,
+        });
+
+        assert_eq!(read.get(500).unwrap(), data[500]);
+        let copy = read.clone();
+        assert_eq!(read.read_all().unwrap(), &data[..]);
+        read.shrink_to_fit();
+        assert_eq!(read.get(999).unwrap(), data[999]);
+
+        let mut copy = copy;
+        assert_eq!(copy.read_all().unwrap(), &data[..]);
+    }
+
+    #[test]
+    fn test_stats() {
+        let data: Vec<u8> = (0..100).collect();
+        let mut read = GreedyAccessReader::new(Chunks {
+            data: &data,
+            size: 30,
+        });
+
+        assert_eq!(read.get(40).unwrap(), 40);
+        let stats = read.stats();
+        assert_eq!(stats.bytes_fetched, 60);
+        assert_eq!(stats.read_calls, 2);
+        assert_eq!(stats.zero_length_reads, 0);
+        assert_eq!(stats.retained, 60);
+        assert_eq!(stats.consumed, 0);
+        assert!(stats.capacity >= 60);
+
+        read.seek(SeekFrom::Start(50)).unwrap();
+        assert_eq!(read.len().unwrap(), 100);
+        let stats = read.stats();
+        assert_eq!(stats.bytes_fetched, 100);
+        assert_eq!(stats.zero_length_reads, 1);
+        assert_eq!(stats.consumed, 50);
+
+        // clearing releases the memory of the dropped data
+        read.clear();
+        let peak = stats.peak_capacity;
+        let stats = read.stats();
+        assert_eq!(stats.retained, 50);
+        assert_eq!(stats.peak_capacity, peak);
+        assert!(stats.capacity < peak);
+
+        read.reset_stats();
+        let stats = read.stats();
+        assert_eq!(stats.bytes_fetched, 0);
+        assert_eq!(stats.read_calls, 0);
+        assert_eq!(stats.peak_capacity, stats.capacity);
+    }
+
+    #[test]
+    fn growth_drops_discarded_data() {
+        let data: Vec<u8> = (0..=255).cycle().take(4096).collect();
+        let mut read = GreedyAccessReader::with_capacity(&data[..], 1024);
+        let mut chunk = [0; 1000];
+        for _ in 0..4 {
+            read.read_exact(&mut chunk).unwrap();
+            read.discard_before(read.consumed);
+        }
+        // the buffer never had to grow, as consumed data was dropped instead
+        assert_eq!(read.stats().peak_capacity, 1024);
+        assert_eq!(read.read_all().unwrap(), &data[4000..]);
+    }
+
+    /// A sink which fails on its first write.
+    struct Flaky {
+        failed: bool,
+        data: Vec<u8>,
+    }
+
+    impl Write for Flaky {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if !self.failed {
+                self.failed = true;
+                return Err(std::io::Error::other("flaky"));
+            }
+            self.data.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_tee() {
+        let data: Vec<u8> = (0..200).collect();
+        let mut read = GreedyAccessReader::with_tee(
+            Chunks {
+                data: &data,
+                size: 7,
+            },
+            Vec::new(),
+        );
+
+        assert_eq!(read.get(20).unwrap(), 20);
+        let mut chunk = [0; 30];
+        read.read_exact(&mut chunk).unwrap();
+        assert_eq!(read.slice(10..50).unwrap(), &data[10..50]);
+        read.clear();
+        assert_eq!(read.read_all().unwrap().len(), 170);
+        assert_eq!(read.tee(), &data);
+
+        let mut read = GreedyAccessReader::with_tee(
+            &data[..],
+            Flaky {
+                failed: false,
+                data: Vec::new(),
+            },
+        );
+        match read.get(10) {
+            Err(Error::Tee { index: 0, source }) => assert_eq!(source.kind(), ErrorKind::Other),
+            e => panic!("unexpected result {:?}", e),
+        }
+        let mut all = Vec::new();
+        read.read_to_end(&mut all).unwrap();
+        assert_eq!(all, data);
+        assert_eq!(read.tee().data, data);
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let data: Vec<u8> = (0..=255).collect();
+        let mut read = GreedyAccessReader::new(&data[..100]);
+
+        let mut chunk = [0; 20];
+        read.read_exact(&mut chunk).unwrap();
+        read.clear();
+        assert_eq!(read.get(30).unwrap(), 50);
+        read.read_exact(&mut chunk).unwrap();
+
+        let mut snapshot = Vec::new();
+        read.write_snapshot(&mut snapshot).unwrap();
+        let position = read.source_position() as usize;
+        assert!(position > 50);
+        drop(read);
+
+        let mut read = GreedyAccessReader::from_snapshot(&snapshot[..], &data[position..]).unwrap();
+        assert_eq!(read.get(0).unwrap(), 20);
+        assert_eq!(read.stats().bytes_fetched, 0);
+        let mut rest = Vec::new();
+        read.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, &data[40..]);
+
+        let e = GreedyAccessReader::from_snapshot(&snapshot[..50], &data[..]).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::UnexpectedEof);
+        let e = GreedyAccessReader::from_snapshot(&data[..], &data[..]).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn snapshot_with_stable_offsets() {
+        let data: Vec<u8> = (0..=255).collect();
+        let mut read = GreedyAccessReaderBuilder::new()
+            .stable_offsets(true)
+            .build(&data[..100]);
+
+        let mut chunk = [0; 20];
+        read.read_exact(&mut chunk).unwrap();
+        read.clear();
+        assert_eq!(read.get(30).unwrap(), 30);
+
+        let mut snapshot = Vec::new();
+        read.write_snapshot(&mut snapshot).unwrap();
+        let position = read.source_position() as usize;
+        drop(read);
+
+        let mut read = GreedyAccessReader::from_snapshot(&snapshot[..], &data[position..]).unwrap();
+        assert_eq!(read.get(30).unwrap(), 30);
+        assert_eq!(read.base_offset(), 20);
+        assert!(matches!(read.get(10), Err(Error::Evicted { .. })));
+        assert_eq!(read.len().unwrap(), 256);
+    }
+
+    #[test]
+    fn test_stable_offsets() {
+        let data: Vec<u8> = (0..=255).collect();
+        let mut read = GreedyAccessReaderBuilder::new()
+            .stable_offsets(true)
+            .build(&data[..]);
+
+        let mut chunk = [0; 20];
+        read.read_exact(&mut chunk).unwrap();
+        assert_eq!(read.get(40).unwrap(), 40);
+        read.clear();
+        assert_eq!(read.base_offset(), 20);
+        assert!(matches!(
+            read.get(5),
+            Err(Error::Evicted {
+                index: 5,
+                lowest: 20
+            })
+        ));
+        assert!(matches!(read.slice(10..30), Err(Error::Evicted { .. })));
+        assert_eq!(read.get(40).unwrap(), 40);
+        assert_eq!(read.slice(20..24).unwrap(), &[20, 21, 22, 23]);
+        assert_eq!(read.get_u16_be(30).unwrap(), 0x1E1F);
+
+        assert_eq!(read.seek(SeekFrom::Start(30)).unwrap(), 30);
+        assert!(read.seek(SeekFrom::Start(10)).is_err());
+        read.discard_before(100);
+        assert_eq!(read.base_offset(), 30);
+        read.read_exact(&mut chunk).unwrap();
+        assert_eq!(chunk[0], 30);
+        assert_eq!(read.stream_position().unwrap(), 50);
+        assert_eq!(read.stats().consumed, 50);
+        assert_eq!(read.len().unwrap(), 256);
+        assert_eq!(read.slice(250..).unwrap(), &data[250..]);
+    }
+
+    #[test]
+    fn test_discard_before() {
+        let data: Vec<u8> = (0..=255).collect();
+        let mut read = GreedyAccessReader::new(&data[..]);
+
+        let mut chunk = [0; 20];
+        read.read_exact(&mut chunk).unwrap();
+        read.discard_before(8);
+        assert_eq!(read.get(0).unwrap(), 8);
+        assert_eq!(read.stream_position().unwrap(), 12);
+
+        let checkpoint = read.checkpoint();
+        read.read_exact(&mut chunk).unwrap();
+        read.discard_before(30);
+        assert_eq!(read.get(0).unwrap(), 20);
+        read.rewind(checkpoint);
+        read.read_exact(&mut chunk).unwrap();
+        assert_eq!(chunk[0], 20);
+    }
+
+    #[test]
+    fn discard_is_lazy() {
+        let data: Vec<u8> = (0..=255).collect();
+        let mut read = GreedyAccessReader::with_capacity(&data[..], 256);
+        assert_eq!(read.get(255).unwrap(), 255);
+
+        let mut chunk = [0; 64];
+        read.read_exact(&mut chunk).unwrap();
+        read.discard_before(64);
+        // the discarded bytes are still in the buffer
+        assert_eq!(read.filled, 256);
+        assert_eq!(read.stats().retained, 192);
+        assert_eq!(read.slice(..4).unwrap(), &[64, 65, 66, 67]);
+
+        read.read_exact(&mut chunk).unwrap();
+        read.clear();
+        // now that most of the buffer was discarded, it is compacted
+        assert_eq!(read.filled, 128);
+        assert_eq!(read.get(0).unwrap(), 128);
+        assert_eq!(read.read_all().unwrap(), &data[128..]);
+        assert_eq!(read.into_buffer(), &data[128..]);
+    }
+
+    #[test]
+    fn test_find() {
+        let data = b"GET / HTTP/1.1\r\nHost: example\r\n\r\nbody";
+        let mut read = GreedyAccessReader::new(Chunks {
+            data: &data[..],
+            size: 3,
+        });
+
+        assert_eq!(read.find(b"\r\n", 0).unwrap(), Some(14));
+        // nothing beyond the match was fetched
+        assert!(read.stats().retained < 20);
+        assert_eq!(read.find(b"\r\n", 15).unwrap(), Some(29));
+        assert_eq!(read.find(b"\r\n\r\n", 0).unwrap(), Some(29));
+        assert_eq!(read.find_byte(b':', 0).unwrap(), Some(20));
+        assert_eq!(read.find(b"body", 0).unwrap(), Some(33));
+        assert_eq!(read.find(b"", 37).unwrap(), Some(37));
+        assert_eq!(read.find(b"bodyx", 0).unwrap(), None);
+        assert_eq!(read.find_byte(b'G', 1).unwrap(), None);
+        assert_eq!(read.find(b"", 38).unwrap(), None);
+
+        let mut read = GreedyAccessReaderBuilder::new()
+            .stable_offsets(true)
+            .build(&data[..]);
+        let mut line = [0; 16];
+        read.read_exact(&mut line).unwrap();
+        read.clear();
+        assert_eq!(read.find(b"\r\n", 16).unwrap(), Some(29));
+        assert!(matches!(
+            read.find_byte(b'G', 0),
+            Err(Error::Evicted { .. })
+        ));
+    }
+
+    #[test]
+    fn test_replay_reader() {
+        let data: Vec<u8> = (0..100).collect();
+        let source = Chunks {
+            data: &data[..],
+            size: 7,
+        };
+
+        let mut read = GreedyAccessReader::new(source.clone());
+        assert_eq!(read.slice(..4).unwrap(), &[0, 1, 2, 3]);
+        let mut replay = read.into_replay_reader();
+        let mut out = Vec::new();
+        replay.read_to_end(&mut out).unwrap();
+        assert_eq!(out, data);
+
+        let mut read = GreedyAccessReader::new(source);
+        let mut header = [0; 10];
+        read.read_exact(&mut header).unwrap();
+        assert_eq!(read.get(20).unwrap(), 20);
+        let mut replay = read.into_replay_reader_at_position();
+        let mut out = Vec::new();
+        replay.read_to_end(&mut out).unwrap();
+        assert_eq!(out, &data[10..]);
+    }
 }
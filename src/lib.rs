@@ -35,6 +35,122 @@
 //! # }
 //! # run().unwrap();
 //! ```
+//!
+//! Random access methods fail with an [`Error`], which tells apart attempts
+//! to access data beyond the end of the source from failures to read from
+//! it. It can be converted into an I/O error as well.
+//!
+//! [`Error`]: ./enum.Error.html
+//!
+//! A [`GreedyAccessReader`] can also be turned into a [`GreedyCursor`],
+//! which can be cloned into multiple cursors reading the same data at
+//! different positions, while fetching it from the source only once.
+//!
+//! [`GreedyCursor`]: ./struct.GreedyCursor.html
+//!
+//! For text streams, [`LineAccessReader`] indexes the lines of the data as
+//! it is fetched, providing access to any line by its number.
+//!
+//! [`LineAccessReader`]: ./struct.LineAccessReader.html
+//!
+//! Similarly, [`RecordAccessReader`] indexes the records of a stream framed
+//! by length prefixes or made of fixed size records.
+//!
+//! [`RecordAccessReader`]: ./struct.RecordAccessReader.html
+//!
+//! When the data is too large to be kept in memory, a
+//! [`ResettableAccessReader`] can be used instead. It only keeps a small
+//! window of the data, and resets the source to the beginning whenever an
+//! earlier position is requested. Any source implementing [`Reset`] is
+//! supported, including all seekable readers. Sources which cannot seek can
+//! be reopened from a function instead.
+//!
+//! [`ResettableAccessReader`]: ./struct.ResettableAccessReader.html
+//! [`Reset`]: ./trait.Reset.html
+//!
+//! ```
+//! # use bra::ResettableAccessReader;
+//! # fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let data: Vec<u8> = (0..=255).collect();
+//! let mut reader = ResettableAccessReader::from_fn(|| Ok(&data[..]));
+//!
+//! assert_eq!(reader.get(200)?, 200);
+//! // going back starts another pass over the data
+//! assert_eq!(reader.slice(4..8)?, &[4, 5, 6, 7]);
+//! # Ok(())
+//! # }
+//! # run().unwrap();
+//! ```
+//!
+//! If only a limited amount of backtracking is needed, a
+//! [`WindowAccessReader`] keeps memory bounded by automatically evicting the
+//! data which is too far behind the reading position.
+//!
+//! [`WindowAccessReader`]: ./struct.WindowAccessReader.html
+//!
+//! For very large streams, [`SegmentedAccessReader`] retains all data in
+//! separate segments, which are never moved or copied as more data is
+//! fetched.
+//!
+//! [`SegmentedAccessReader`]: ./struct.SegmentedAccessReader.html
+//!
+//! [`SpillingAccessReader`] also retains all data, but moves the older part
+//! of it to a temporary file once a memory threshold is exceeded.
+//!
+//! [`SpillingAccessReader`]: ./struct.SpillingAccessReader.html
+//!
+//! For sources which can seek, such as files, [`SparseAccessReader`] offers
+//! the same random access methods while only fetching the pages of data
+//! which are actually accessed, and keeping them in a bounded cache.
+//!
+//! [`SparseAccessReader`]: ./struct.SparseAccessReader.html
+//!
+//! [`ArenaAccessReader`] never moves fetched data, so that slices of it can
+//! be obtained through a shared reference and kept alive at the same time.
+//!
+//! [`ArenaAccessReader`]: ./struct.ArenaAccessReader.html
+//!
+//! To access the same data from several threads, [`SyncAccessReader`]
+//! provides random access through a shared reference. Fetched data is read
+//! without locking, and slices of it remain valid while more data is
+//! fetched by other threads.
+//!
+//! [`SyncAccessReader`]: ./struct.SyncAccessReader.html
+//!
+//! # Features
+//!
+//! - `tokio`: provides `AsyncGreedyAccessReader`, an asynchronous
+//!   counterpart of [`GreedyAccessReader`] for Tokio's `AsyncRead` sources.
 
+mod arena;
+#[cfg(feature = "tokio")]
+mod async_greedy;
+mod cursor;
+mod error;
 mod greedy;
-pub use crate::greedy::GreedyAccessReader;
+mod lines;
+mod position;
+mod records;
+mod resettable;
+mod segmented;
+mod sparse;
+mod spill;
+mod stable;
+mod sync;
+#[cfg(test)]
+mod test_util;
+mod window;
+pub use crate::arena::ArenaAccessReader;
+#[cfg(feature = "tokio")]
+pub use crate::async_greedy::AsyncGreedyAccessReader;
+pub use crate::cursor::GreedyCursor;
+pub use crate::error::{Error, Result};
+pub use crate::greedy::{Checkpoint, GreedyAccessReader, GreedyAccessReaderBuilder, Growth, Stats};
+pub use crate::lines::LineAccessReader;
+pub use crate::records::{Framing, RecordAccessReader, Records};
+pub use crate::resettable::{Reopen, Reset, ResettableAccessReader};
+pub use crate::segmented::SegmentedAccessReader;
+pub use crate::sparse::SparseAccessReader;
+pub use crate::spill::SpillingAccessReader;
+pub use crate::sync::SyncAccessReader;
+pub use crate::window::WindowAccessReader;
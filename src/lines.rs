@@ -0,0 +1,197 @@
+use crate::error::Result;
+use crate::greedy::GreedyAccessReader;
+use std::io::Read;
+use std::ops::{Bound, RangeBounds};
+
+/// A line index over a greedy buffered reader, for random access to the
+/// lines of a text stream.
+///
+/// The offsets at which lines start are recorded as the data is fetched,
+/// and only as far as needed to find the requested lines. Lines may be
+/// terminated by either LF or CRLF, and the terminator is not included in
+/// the lines obtained. As with [`str::lines`], a final line terminator does
+/// not start another, empty line.
+///
+/// Line numbers and columns start at 0, and columns are counted in bytes.
+///
+/// ```
+/// # use bra::LineAccessReader;
+/// # fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// let text = "first\r\nsecond\nthird";
+/// let mut reader = LineAccessReader::new(text.as_bytes());
+///
+/// assert_eq!(reader.line(1)?, Some(&b"second"[..]));
+/// assert_eq!(reader.position(10)?, Some((1, 3)));
+/// assert_eq!(reader.lines(1..)?, vec![&b"second"[..], &b"third"[..]]);
+/// # Ok(())
+/// # }
+/// # run().unwrap();
+/// ```
+///
+/// [`str::lines`]: https://doc.rust-lang.org/std/primitive.str.html#method.lines
+#[derive(Debug)]
+pub struct LineAccessReader<R> {
+    reader: GreedyAccessReader<R>,
+    /// the offsets of the line starts found so far
+    starts: Vec<usize>,
+    /// the total length of the data, once all of it was scanned
+    len: Option<usize>,
+}
+
+impl<R> LineAccessReader<R>
+where
+    R: Read,
+{
+    /// Creates a new line index over the given byte source.
+    pub fn new(src: R) -> Self {
+        LineAccessReader {
+            reader: GreedyAccessReader::new(src),
+            starts: vec![0],
+            len: None,
+        }
+    }
+
+    /// Retrieves the underlying greedy reader, with all data fetched so
+    /// far.
+    pub fn into_inner(self) -> GreedyAccessReader<R> {
+        self.reader
+    }
+
+    /// Obtains the line with the given number, or `None` if the data has
+    /// fewer lines.
+    pub fn line(&mut self, n: usize) -> Result<Option<&[u8]>> {
+        self.index_lines(n.saturating_add(1))?;
+        let this = &*self;
+        Ok(this.bounds(n).and_then(|(b, e)| this.reader.buffered(b, e)))
+    }
+
+    /// Obtains the lines in the given range of line numbers. Lines beyond
+    /// the end of the data are left out.
+    pub fn lines<T>(&mut self, range: T) -> Result<Vec<&[u8]>>
+    where
+        T: RangeBounds<usize>,
+    {
+        let b = match range.start_bound() {
+            Bound::Unbounded => 0,
+            Bound::Excluded(&b) => b + 1,
+            Bound::Included(&b) => b,
+        };
+        let e = match range.end_bound() {
+            Bound::Unbounded => usize::MAX,
+            Bound::Excluded(&e) => e,
+            Bound::Included(&e) => e.saturating_add(1),
+        };
+
+        self.index_lines(e)?;
+        let this = &*self;
+        let e = usize::min(e, this.starts.len());
+        Ok((b..e)
+            .filter_map(|n| this.bounds(n))
+            .filter_map(|(b, e)| this.reader.buffered(b, e))
+            .collect())
+    }
+
+    /// Obtains the total number of lines, reading the source until the end.
+    pub fn line_count(&mut self) -> Result<usize> {
+        self.index_lines(usize::MAX)?;
+        let len = self.len.unwrap_or_default();
+        match self.starts.last() {
+            Some(&last) if last == len => Ok(self.starts.len() - 1),
+            _ => Ok(self.starts.len()),
+        }
+    }
+
+    /// Maps a byte offset to the line containing it and its column in that
+    /// line, or `None` if the offset is beyond the end of the data.
+    ///
+    /// The terminator of a line is considered to be part of it.
+    pub fn position(&mut self, offset: usize) -> Result<Option<(usize, usize)>> {
+        while self.len.is_none() && self.starts.last().map_or(true, |&s| s <= offset) {
+            self.scan_line()?;
+        }
+        if self.len.is_some_and(|len| offset >= len) {
+            return Ok(None);
+        }
+        let line = self.starts.partition_point(|&s| s <= offset) - 1;
+        Ok(Some((line, offset - self.starts[line])))
+    }
+
+    /// Scans the data until the start of line `n` is known, or the source
+    /// ends.
+    fn index_lines(&mut self, n: usize) -> Result<()> {
+        while self.len.is_none() && self.starts.len() <= n {
+            self.scan_line()?;
+        }
+        Ok(())
+    }
+
+    /// Finds the start of the next line.
+    fn scan_line(&mut self) -> Result<()> {
+        let from = self.starts.last().copied().unwrap_or_default();
+        match self.reader.find_byte(b'\n', from)? {
+            Some(i) => self.starts.push(i + 1),
+            None => self.len = Some(self.reader.len()?),
+        }
+        Ok(())
+    }
+
+    /// The start and end offsets of line `n`, excluding the terminator, if
+    /// it was scanned.
+    fn bounds(&self, n: usize) -> Option<(usize, usize)> {
+        let b = *self.starts.get(n)?;
+        match self.starts.get(n + 1) {
+            Some(&next) => {
+                let mut e = next - 1;
+                if e > b && self.reader.buffered(e - 1, e) == Some(b"\r") {
+                    e -= 1;
+                }
+                Some((b, e))
+            }
+            None => self.len.filter(|&len| len > b).map(|len| (b, len)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LineAccessReader;
+
+    #[test]
+    fn test_lines() {
+        let text = b"alpha\nbeta\r\n\ngamma\r\n";
+        let mut read = LineAccessReader::new(&text[..]);
+
+        assert_eq!(read.line(0).unwrap(), Some(&b"alpha"[..]));
+        assert_eq!(read.line(3).unwrap(), Some(&b"gamma"[..]));
+        assert_eq!(read.line(2).unwrap(), Some(&b""[..]));
+        assert_eq!(read.line(1).unwrap(), Some(&b"beta"[..]));
+        assert_eq!(read.line(4).unwrap(), None);
+        assert_eq!(read.lines(1..=2).unwrap(), vec![&b"beta"[..], &b""[..]]);
+        assert_eq!(read.lines(2..10).unwrap().len(), 2);
+        assert_eq!(read.line_count().unwrap(), 4);
+
+        let mut read = LineAccessReader::new(&b"no terminator"[..]);
+        assert_eq!(read.line_count().unwrap(), 1);
+        assert_eq!(read.line(0).unwrap(), Some(&b"no terminator"[..]));
+
+        let mut read = LineAccessReader::new(&b""[..]);
+        assert_eq!(read.line_count().unwrap(), 0);
+        assert_eq!(read.line(0).unwrap(), None);
+        assert_eq!(read.position(0).unwrap(), None);
+    }
+
+    #[test]
+    fn test_position() {
+        let text = b"alpha\nbeta\r\n\ngamma";
+        let mut read = LineAccessReader::new(&text[..]);
+
+        assert_eq!(read.position(0).unwrap(), Some((0, 0)));
+        assert_eq!(read.position(5).unwrap(), Some((0, 5)));
+        assert_eq!(read.position(8).unwrap(), Some((1, 2)));
+        assert_eq!(read.position(11).unwrap(), Some((1, 5)));
+        assert_eq!(read.position(12).unwrap(), Some((2, 0)));
+        assert_eq!(read.position(17).unwrap(), Some((3, 4)));
+        assert_eq!(read.position(18).unwrap(), None);
+        assert_eq!(read.position(3).unwrap(), Some((0, 3)));
+    }
+}
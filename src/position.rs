@@ -0,0 +1,115 @@
+use crate::error::{Error, Result};
+use std::convert::TryFrom;
+use std::io::{Error as IoError, ErrorKind as IoErrorKind, Result as IoResult, SeekFrom};
+use std::ops::{Bound, RangeBounds};
+
+/// Resolves the boundaries of a range of indices into its start and its
+/// end, the latter being `None` if the range is not bound at the end.
+///
+/// A range not bound at the start begins at `origin`. An inclusive end of
+/// `usize::MAX` saturates, leaving the range out of bounds of any data.
+pub(crate) fn resolve_range<T>(range: &T, origin: usize) -> (usize, Option<usize>)
+where
+    T: RangeBounds<usize>,
+{
+    let b = match range.start_bound() {
+        Bound::Unbounded => origin,
+        Bound::Excluded(&b) | Bound::Included(&b) => b,
+    };
+    let e = match range.end_bound() {
+        Bound::Unbounded => None,
+        Bound::Excluded(&e) => Some(e),
+        Bound::Included(&e) => Some(e.saturating_add(1)),
+    };
+    (b, e)
+}
+
+/// Resolves the end of a range not bound at the end to the length of the
+/// data.
+///
+/// # Error
+///
+/// Fails with [`Error::OutOfBounds`] if the range starts past the end of
+/// the data, rather than leaving a reversed range which the caller never
+/// wrote.
+///
+/// [`Error::OutOfBounds`]: ./enum.Error.html#variant.OutOfBounds
+pub(crate) fn open_end(b: usize, len: usize) -> Result<usize> {
+    if b > len {
+        Err(Error::OutOfBounds {
+            range: b..b,
+            available: len,
+        })
+    } else {
+        Ok(len)
+    }
+}
+
+/// Resolves the target position of a seek from the current position. The
+/// length of the data is only obtained for seeks relative to the end.
+///
+/// # Error
+///
+/// Fails with `InvalidInput` if the target is negative or does not fit in a
+/// `usize`, or with the error of `len`.
+pub(crate) fn seek_target<F>(pos: SeekFrom, current: usize, len: F) -> IoResult<usize>
+where
+    F: FnOnce() -> IoResult<usize>,
+{
+    let target = match pos {
+        SeekFrom::Start(o) => Some(o),
+        SeekFrom::Current(o) => (current as u64).checked_add_signed(o),
+        SeekFrom::End(o) => (len()? as u64).checked_add_signed(o),
+    };
+    target.and_then(|p| usize::try_from(p).ok()).ok_or_else(|| {
+        IoError::new(
+            IoErrorKind::InvalidInput,
+            "invalid seek to a negative or overflowing position",
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{open_end, resolve_range, seek_target};
+    use crate::Error;
+    use std::io::{ErrorKind, SeekFrom};
+
+    #[test]
+    fn test_resolve_range() {
+        assert_eq!(resolve_range(&(..), 0), (0, None));
+        assert_eq!(resolve_range(&(..), 7), (7, None));
+        assert_eq!(resolve_range(&(2..5), 7), (2, Some(5)));
+        assert_eq!(resolve_range(&(2..=5), 0), (2, Some(6)));
+        assert_eq!(resolve_range(&(3..), 0), (3, None));
+        assert_eq!(resolve_range(&(..=usize::MAX), 0), (0, Some(usize::MAX)));
+    }
+
+    #[test]
+    fn test_open_end() {
+        assert_eq!(open_end(0, 17).unwrap(), 17);
+        assert_eq!(open_end(17, 17).unwrap(), 17);
+        assert!(matches!(
+            open_end(18, 17),
+            Err(Error::OutOfBounds { range, available: 17 }) if range == (18..18)
+        ));
+    }
+
+    #[test]
+    fn test_seek_target() {
+        let len = || Ok(100);
+        assert_eq!(seek_target(SeekFrom::Start(5), 10, len).unwrap(), 5);
+        assert_eq!(seek_target(SeekFrom::Current(-4), 10, len).unwrap(), 6);
+        assert_eq!(seek_target(SeekFrom::End(-1), 10, len).unwrap(), 99);
+        assert_eq!(seek_target(SeekFrom::End(1), 10, len).unwrap(), 101);
+        assert_eq!(
+            seek_target(SeekFrom::Current(-11), 10, len)
+                .unwrap_err()
+                .kind(),
+            ErrorKind::InvalidInput
+        );
+        // the length is only needed for seeks from the end
+        let fail = || panic!("length requested");
+        assert_eq!(seek_target(SeekFrom::Current(0), 3, fail).unwrap(), 3);
+    }
+}
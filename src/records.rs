@@ -0,0 +1,368 @@
+use crate::error::{Error, Result};
+use crate::greedy::GreedyAccessReader;
+use std::convert::TryFrom;
+use std::io::{Error as IoError, ErrorKind as IoErrorKind, Read};
+use std::ops::{Bound, RangeBounds};
+use std::slice;
+
+/// The amount of record data fetched at once before more of the same
+/// record was found, in bytes.
+const MIN_RECORD_FETCH: usize = 64 * 1024;
+
+/// The way in which records are delimited in a stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Framing {
+    /// Each record is preceded by its length as a single byte.
+    U8,
+    /// Each record is preceded by its length as a little endian `u16`.
+    U16Le,
+    /// Each record is preceded by its length as a big endian `u16`.
+    U16Be,
+    /// Each record is preceded by its length as a little endian `u32`.
+    U32Le,
+    /// Each record is preceded by its length as a big endian `u32`.
+    U32Be,
+    /// Each record is preceded by its length as an unsigned LEB128 varint.
+    Leb128,
+    /// All records have the given length, without any prefix.
+    Fixed(usize),
+}
+
+/// A record index over a greedy buffered reader, for random access to the
+/// records of a framed stream.
+///
+/// The boundaries of the records are recorded as the data is fetched, and
+/// only as far as needed to find the requested records. Records are
+/// obtained as slices of the buffer, without their length prefix.
+///
+/// Record numbers start at 0.
+///
+/// ```
+/// # use bra::{Framing, RecordAccessReader};
+/// # fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// let data = b"\x03one\x00\x05three";
+/// let mut reader = RecordAccessReader::new(&data[..], Framing::U8);
+///
+/// assert_eq!(reader.record(2)?, Some(&b"three"[..]));
+/// assert_eq!(reader.record(3)?, None);
+/// for record in reader.records(..2)? {
+///     assert!(record.len() <= 3);
+/// }
+/// # Ok(())
+/// # }
+/// # run().unwrap();
+/// ```
+#[derive(Debug)]
+pub struct RecordAccessReader<R> {
+    reader: GreedyAccessReader<R>,
+    framing: Framing,
+    /// the start and end offsets of the records found so far
+    bounds: Vec<(usize, usize)>,
+    /// the offset of the first record not yet found
+    next: usize,
+    /// whether the source ended after the last record found
+    done: bool,
+}
+
+impl<R> RecordAccessReader<R>
+where
+    R: Read,
+{
+    /// Creates a new record index over the given byte source.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the framing is `Framing::Fixed(0)`.
+    pub fn new(src: R, framing: Framing) -> Self {
+        assert!(
+            framing != Framing::Fixed(0),
+            "fixed size records must not be empty"
+        );
+        RecordAccessReader {
+            reader: GreedyAccessReader::new(src),
+            framing,
+            bounds: Vec::new(),
+            next: 0,
+            done: false,
+        }
+    }
+
+    /// Retrieves the underlying greedy reader, with all data fetched so
+    /// far.
+    pub fn into_inner(self) -> GreedyAccessReader<R> {
+        self.reader
+    }
+
+    /// Obtains the record with the given number, or `None` if the data has
+    /// fewer records.
+    ///
+    /// # Error
+    ///
+    /// Returns [`Error::OutOfBounds`] if the data source ends in the middle
+    /// of a record or of its prefix, or [`Error::Io`] if reading from the
+    /// source fails or a length prefix does not fit in a `usize`.
+    ///
+    /// [`Error::OutOfBounds`]: ./enum.Error.html#variant.OutOfBounds
+    /// [`Error::Io`]: ./enum.Error.html#variant.Io
+    pub fn record(&mut self, n: usize) -> Result<Option<&[u8]>> {
+        self.index_records(n.saturating_add(1))?;
+        let this = &*self;
+        Ok(this
+            .bounds
+            .get(n)
+            .and_then(|&(b, e)| this.reader.buffered(b, e)))
+    }
+
+    /// Obtains an iterator over the records in the given range of record
+    /// numbers, which borrows them from the buffer. Records beyond the end
+    /// of the data are left out.
+    ///
+    /// If the range's end is not bound (e.g. `5..`), the source is read
+    /// until the end.
+    pub fn records<T>(&mut self, range: T) -> Result<Records<'_>>
+    where
+        T: RangeBounds<usize>,
+    {
+        let b = match range.start_bound() {
+            Bound::Unbounded => 0,
+            Bound::Excluded(&b) => b + 1,
+            Bound::Included(&b) => b,
+        };
+        let e = match range.end_bound() {
+            Bound::Unbounded => usize::MAX,
+            Bound::Excluded(&e) => e,
+            Bound::Included(&e) => e.saturating_add(1),
+        };
+
+        self.index_records(e)?;
+        let e = usize::min(e, self.bounds.len());
+        let bounds = self.bounds.get(b..e).unwrap_or_default();
+        Ok(Records {
+            data: self.reader.buffered(0, self.next).unwrap_or_default(),
+            bounds: bounds.iter(),
+        })
+    }
+
+    /// Obtains the total number of records, reading the source until the
+    /// end.
+    pub fn record_count(&mut self) -> Result<usize> {
+        self.index_records(usize::MAX)?;
+        Ok(self.bounds.len())
+    }
+
+    /// Scans the data until `n` records are known, or the source ends.
+    fn index_records(&mut self, n: usize) -> Result<()> {
+        while !self.done && self.bounds.len() < n {
+            self.scan_record()?;
+        }
+        Ok(())
+    }
+
+    /// Finds the bounds of the next record, fetching all of its data.
+    fn scan_record(&mut self) -> Result<()> {
+        let at = self.next;
+        let first = match self.reader.get(at) {
+            Ok(v) => v,
+            Err(Error::OutOfBounds { .. }) => {
+                self.done = true;
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+
+        let (prefix, len) = match self.framing {
+            Framing::U8 => (1, usize::from(first)),
+            Framing::U16Le => (2, usize::from(self.reader.get_u16_le(at)?)),
+            Framing::U16Be => (2, usize::from(self.reader.get_u16_be(at)?)),
+            Framing::U32Le => (4, to_usize(u64::from(self.reader.get_u32_le(at)?))?),
+            Framing::U32Be => (4, to_usize(u64::from(self.reader.get_u32_be(at)?))?),
+            Framing::Leb128 => self.get_leb128(at)?,
+            Framing::Fixed(len) => (0, len),
+        };
+
+        let b = at + prefix;
+        let e = b.checked_add(len).ok_or_else(invalid_length)?;
+        self.fetch_record(b, e)?;
+        self.bounds.push((b, e));
+        self.next = e;
+        Ok(())
+    }
+
+    /// Fetches the data of the record from `b` to `e`.
+    ///
+    /// The length of a record comes from the data itself, so it is fetched
+    /// in steps of at most the size fetched so far, to avoid reserving
+    /// memory for a corrupt length which the data does not back.
+    fn fetch_record(&mut self, b: usize, e: usize) -> Result<()> {
+        let mut end = b;
+        while end < e {
+            end = usize::min(e, end + usize::max(end - b, MIN_RECORD_FETCH));
+            if let Err(err) = self.reader.get(end - 1) {
+                return Err(match err {
+                    Error::OutOfBounds { available, .. } => Error::OutOfBounds {
+                        range: b..e,
+                        available,
+                    },
+                    err => err,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Decodes an unsigned LEB128 varint at the given index, returning its
+    /// size in bytes and its value.
+    fn get_leb128(&mut self, index: usize) -> Result<(usize, usize)> {
+        let mut value = 0u64;
+        let mut size = 0;
+        loop {
+            let byte = self.reader.get(index + size)?;
+            let bits = u64::from(byte & 0x7f);
+            let shift = 7 * size as u32;
+            if shift >= u64::BITS || (bits << shift) >> shift != bits {
+                return Err(invalid_length());
+            }
+            value |= bits << shift;
+            size += 1;
+            if byte & 0x80 == 0 {
+                return Ok((size, to_usize(value)?));
+            }
+        }
+    }
+}
+
+/// An iterator over records borrowed from the buffer of a
+/// [`RecordAccessReader`].
+///
+/// [`RecordAccessReader`]: ./struct.RecordAccessReader.html
+#[derive(Debug, Clone)]
+pub struct Records<'a> {
+    data: &'a [u8],
+    bounds: slice::Iter<'a, (usize, usize)>,
+}
+
+impl<'a> Iterator for Records<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        self.bounds.next().map(|&(b, e)| &self.data[b..e])
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.bounds.size_hint()
+    }
+}
+
+impl<'a> DoubleEndedIterator for Records<'a> {
+    fn next_back(&mut self) -> Option<&'a [u8]> {
+        self.bounds.next_back().map(|&(b, e)| &self.data[b..e])
+    }
+}
+
+impl<'a> ExactSizeIterator for Records<'a> {}
+
+/// Converts a record length into a `usize`, failing if it does not fit.
+fn to_usize(len: u64) -> Result<usize> {
+    usize::try_from(len).map_err(|_| invalid_length())
+}
+
+fn invalid_length() -> Error {
+    Error::Io(IoError::new(
+        IoErrorKind::InvalidData,
+        "record length does not fit in usize",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Framing, RecordAccessReader};
+    use crate::Error;
+    use std::io::ErrorKind;
+
+    #[test]
+    fn test_prefixes() {
+        let cases: [(Framing, &[u8]); 6] = [
+            (Framing::U8, b"\x02ab\x00\x03cde"),
+            (Framing::U16Le, b"\x02\x00ab\x00\x00\x03\x00cde"),
+            (Framing::U16Be, b"\x00\x02ab\x00\x00\x00\x03cde"),
+            (
+                Framing::U32Le,
+                b"\x02\x00\x00\x00ab\x00\x00\x00\x00\x03\x00\x00\x00cde",
+            ),
+            (
+                Framing::U32Be,
+                b"\x00\x00\x00\x02ab\x00\x00\x00\x00\x00\x00\x00\x03cde",
+            ),
+            (Framing::Leb128, b"\x02ab\x00\x83\x00cde"),
+        ];
+        for (framing, data) in cases {
+            let mut read = RecordAccessReader::new(data, framing);
+            assert_eq!(read.record(2).unwrap(), Some(&b"cde"[..]), "{:?}", framing);
+            assert_eq!(read.record(0).unwrap(), Some(&b"ab"[..]), "{:?}", framing);
+            assert_eq!(read.record(1).unwrap(), Some(&b""[..]), "{:?}", framing);
+            assert_eq!(read.record(3).unwrap(), None, "{:?}", framing);
+            assert_eq!(read.record_count().unwrap(), 3, "{:?}", framing);
+        }
+
+        let mut read = RecordAccessReader::new(&b"abcdefgh"[..], Framing::Fixed(3));
+        assert!(matches!(
+            read.record(2),
+            Err(Error::OutOfBounds { available: 8, .. })
+        ));
+        assert_eq!(read.record(1).unwrap(), Some(&b"def"[..]));
+    }
+
+    #[test]
+    fn test_records() {
+        let data: Vec<u8> = (0..50u8)
+            .flat_map(|i| std::iter::once(i).chain(vec![i; usize::from(i)]))
+            .collect();
+        let mut read = RecordAccessReader::new(&data[..], Framing::Leb128);
+
+        let records: Vec<_> = read.records(10..20).unwrap().collect();
+        assert_eq!(records.len(), 10);
+        assert_eq!(records[0], &[10; 10]);
+        assert_eq!(records[9], &[19; 19]);
+
+        let mut all = read.records(..).unwrap();
+        assert_eq!(all.len(), 50);
+        assert_eq!(all.next_back(), Some(&[49; 49][..]));
+        assert_eq!(all.next(), Some(&[][..]));
+        assert_eq!(read.records(60..).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_invalid_prefix() {
+        let data = [0xff; 11];
+        let mut read = RecordAccessReader::new(&data[..], Framing::Leb128);
+        match read.record(0) {
+            Err(Error::Io(e)) => assert_eq!(e.kind(), ErrorKind::InvalidData),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let mut read = RecordAccessReader::new(&b"\x05abc"[..], Framing::U8);
+        assert!(matches!(
+            read.record(0),
+            Err(Error::OutOfBounds { available: 4, .. })
+        ));
+    }
+
+    #[test]
+    fn huge_length_prefix() {
+        let data = b"\x80\x80\x80\x80\x80\x80\x80\x80\x01abc";
+        let mut read = RecordAccessReader::new(&data[..], Framing::Leb128);
+        assert!(matches!(
+            read.record(0),
+            Err(Error::OutOfBounds { available: 12, .. })
+        ));
+        assert!(read.into_inner().stats().peak_capacity < 1 << 20);
+
+        let data = b"\xf0\xff\xff\xffabc";
+        let mut read = RecordAccessReader::new(&data[..], Framing::U32Le);
+        assert!(matches!(
+            read.record(0),
+            Err(Error::OutOfBounds { available: 7, .. })
+        ));
+        assert!(read.into_inner().stats().peak_capacity < 1 << 20);
+    }
+}
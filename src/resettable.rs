@@ -0,0 +1,563 @@
+use crate::error::{Error, Result};
+use crate::position::resolve_range;
+use std::fs::File;
+use std::io::{
+    Error as IoError, ErrorKind as IoErrorKind, Read, Result as IoResult, Seek, SeekFrom,
+};
+use std::ops::RangeBounds;
+use std::path::Path;
+
+/// A data source which can be brought back to its beginning.
+///
+/// This trait is implemented for every reader which also implements
+/// [`Seek`], in which case resetting is the same as seeking to the start of
+/// the stream. Sources which cannot seek can be reopened instead with
+/// [`Reopen`].
+///
+/// [`Seek`]: https://doc.rust-lang.org/std/io/trait.Seek.html
+/// [`Reopen`]: ./struct.Reopen.html
+pub trait Reset: Read {
+    /// Resets the source, so that the next read yields the first byte of the
+    /// data stream once again.
+    fn reset(&mut self) -> IoResult<()>;
+}
+
+impl<T> Reset for T
+where
+    T: Read + Seek,
+{
+    fn reset(&mut self) -> IoResult<()> {
+        self.seek(SeekFrom::Start(0)).map(|_| ())
+    }
+}
+
+/// A resettable data source which obtains a new reader from a factory
+/// function on every reset.
+///
+/// The first reader is only created on the first read.
+#[derive(Debug)]
+pub struct Reopen<F, R> {
+    open: F,
+    current: Option<R>,
+}
+
+impl<F, R> Reopen<F, R>
+where
+    F: FnMut() -> IoResult<R>,
+    R: Read,
+{
+    /// Creates a new resettable source out of the given reader factory.
+    pub fn new(open: F) -> Self {
+        Reopen {
+            open,
+            current: None,
+        }
+    }
+}
+
+impl<F, R> Read for Reopen<F, R>
+where
+    F: FnMut() -> IoResult<R>,
+    R: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        if self.current.is_none() {
+            self.current = Some((self.open)()?);
+        }
+        self.current.as_mut().unwrap().read(buf)
+    }
+}
+
+impl<F, R> Reset for Reopen<F, R>
+where
+    F: FnMut() -> IoResult<R>,
+    R: Read,
+{
+    fn reset(&mut self) -> IoResult<()> {
+        self.current = Some((self.open)()?);
+        Ok(())
+    }
+}
+
+/// The default number of bytes kept in memory around the last access.
+const DEFAULT_CAPACITY: usize = 8 * 1024;
+
+/// The number of bytes covered by each checksum when verifying passes.
+const BLOCK_SIZE: usize = 4 * 1024;
+
+/// A random access reader which keeps a way to reset the source to the
+/// beginning, so that it can be streamed again in multiple passes.
+///
+/// Unlike [`GreedyAccessReader`], only a small window of the data around the
+/// last accessed position is kept in memory. Accessing a position ahead of
+/// the source skips over the data in between, whereas accessing a position
+/// behind it resets the source and streams it again from the start. This
+/// makes it suitable for inputs which are too large to be retained as a
+/// whole, at the expense of reading some of the data more than once.
+///
+/// The source is expected to be at the beginning of the data stream when
+/// passed to this construct, and to yield the exact same bytes on every pass.
+/// The latter can be checked with [`with_verification`].
+///
+/// [`GreedyAccessReader`]: ./struct.GreedyAccessReader.html
+/// [`with_verification`]: ./struct.ResettableAccessReader.html#method.with_verification
+#[derive(Debug, Clone)]
+pub struct ResettableAccessReader<S> {
+    src: S,
+    /// the position of the next byte to be read from the source
+    pos: usize,
+    /// the last bytes read from the source, which always end at `pos`
+    buf: Vec<u8>,
+    /// the minimum number of bytes to fetch on each access
+    capacity: usize,
+    /// the total length of the data, once known
+    len: Option<usize>,
+    verifier: Option<Verifier>,
+}
+
+impl<S> ResettableAccessReader<S>
+where
+    S: Reset,
+{
+    /// Creates a new resettable reader with the given byte source.
+    pub fn new(src: S) -> Self {
+        ResettableAccessReader::with_capacity(src, DEFAULT_CAPACITY)
+    }
+
+    /// Creates a new resettable reader with the given byte source and the
+    /// specified buffer capacity.
+    ///
+    /// At least `capacity` bytes are fetched from the source on each access
+    /// which is not already in memory.
+    pub fn with_capacity(src: S, capacity: usize) -> Self {
+        ResettableAccessReader {
+            src,
+            pos: 0,
+            buf: Vec::with_capacity(capacity),
+            capacity: usize::max(capacity, 1),
+            len: None,
+            verifier: None,
+        }
+    }
+
+    /// Creates a new resettable reader which verifies that every pass over
+    /// the given byte source yields the same data.
+    ///
+    /// Only a checksum of each block of data is retained for this purpose.
+    /// Accessing the data fails with an error of kind `InvalidData` once a
+    /// difference is detected.
+    pub fn with_verification(src: S) -> Self {
+        ResettableAccessReader::with_capacity_and_verification(src, DEFAULT_CAPACITY)
+    }
+
+    /// Creates a new resettable reader with the specified buffer capacity,
+    /// which verifies that every pass over the given byte source yields the
+    /// same data.
+    ///
+    /// See [`with_capacity`] and [`with_verification`].
+    ///
+    /// [`with_capacity`]: ./struct.ResettableAccessReader.html#method.with_capacity
+    /// [`with_verification`]: ./struct.ResettableAccessReader.html#method.with_verification
+    pub fn with_capacity_and_verification(src: S, capacity: usize) -> Self {
+        let mut reader = ResettableAccessReader::with_capacity(src, capacity);
+        reader.verifier = Some(Verifier::default());
+        reader
+    }
+
+    /// Retrieves the internal source, discarding the buffer in the process.
+    pub fn into_inner(self) -> S {
+        self.src
+    }
+
+    /// Fetches a single byte from the data source.
+    ///
+    /// # Error
+    ///
+    /// Returns [`Error::OutOfBounds`] if the data source ends before the
+    /// given index, or [`Error::Io`] if reading from the source fails.
+    ///
+    /// [`Error::OutOfBounds`]: ./enum.Error.html#variant.OutOfBounds
+    /// [`Error::Io`]: ./enum.Error.html#variant.Io
+    pub fn get(&mut self, index: usize) -> Result<u8> {
+        let e = Error::range_end(index, 1, self.pos)?;
+        self.load(index, Some(e))?;
+        Ok(self.buf[index - self.buf_start()])
+    }
+
+    /// Obtains a slice of bytes.
+    ///
+    /// If the range's end is not bound, the source is read until the end,
+    /// in which case the whole remainder of the data is kept in memory.
+    ///
+    /// # Error
+    ///
+    /// Returns [`Error::OutOfBounds`] if the data source ends before the end
+    /// of the range, [`Error::InvalidRange`] if the range starts after its
+    /// end, or [`Error::Io`] if reading from the source fails.
+    ///
+    /// [`Error::OutOfBounds`]: ./enum.Error.html#variant.OutOfBounds
+    /// [`Error::InvalidRange`]: ./enum.Error.html#variant.InvalidRange
+    /// [`Error::Io`]: ./enum.Error.html#variant.Io
+    pub fn slice<T>(&mut self, range: T) -> Result<&[u8]>
+    where
+        T: RangeBounds<usize>,
+    {
+        let (b, e) = resolve_range(&range, 0);
+
+        if let Some(e) = e {
+            if b > e {
+                return Err(Error::InvalidRange { range: b..e });
+            }
+        }
+
+        self.load(b, e)?;
+        let start = b - self.buf_start();
+        let end = e.map_or(self.buf.len(), |e| e - self.buf_start());
+        Ok(&self.buf[start..end])
+    }
+
+    fn buf_start(&self) -> usize {
+        self.pos - self.buf.len()
+    }
+
+    /// Ensures that the buffer holds the bytes from `b` to `e`, or from `b`
+    /// until the end of the data if `e` is `None`.
+    fn load(&mut self, b: usize, e: Option<usize>) -> Result<()> {
+        let buf_start = self.buf_start();
+        if b >= buf_start && e.is_some_and(|e| e <= self.pos) {
+            // already in memory
+            return Ok(());
+        }
+
+        let end = e.unwrap_or(b);
+        if let Some(len) = self.len {
+            if end > len || b > len {
+                return Err(Error::OutOfBounds {
+                    range: b..end,
+                    available: len,
+                });
+            }
+        }
+
+        if b < buf_start {
+            // start another pass
+            self.src.reset()?;
+            if let Some(verifier) = &mut self.verifier {
+                verifier.restart();
+            }
+            self.pos = 0;
+            self.buf.clear();
+        }
+
+        if b <= self.pos {
+            // keep what is already in memory
+            let skip = b - self.buf_start();
+            self.buf.drain(..skip);
+        } else {
+            self.buf.clear();
+            self.skip(b - self.pos)?;
+        }
+
+        let mut buf = std::mem::take(&mut self.buf);
+        let r = match e {
+            Some(e) => self.fill(&mut buf, usize::max(e, b.saturating_add(self.capacity)) - b),
+            None => self.fill(&mut buf, usize::MAX),
+        };
+        self.buf = buf;
+        r?;
+
+        if self.pos < end || self.buf_start() != b {
+            return Err(Error::OutOfBounds {
+                range: b..end,
+                available: self.pos,
+            });
+        }
+        Ok(())
+    }
+
+    /// Reads and discards `amount` bytes from the source.
+    fn skip(&mut self, mut amount: usize) -> IoResult<()> {
+        let mut scratch = [0; 4096];
+        while amount > 0 {
+            let l = usize::min(amount, scratch.len());
+            let n = self.read_source(&mut scratch[..l])?;
+            if n == 0 {
+                break;
+            }
+            amount -= n;
+        }
+        Ok(())
+    }
+
+    /// Reads from the source into `buf` until it holds `size` bytes, or the
+    /// end of the data is reached.
+    fn fill(&mut self, buf: &mut Vec<u8>, size: usize) -> IoResult<()> {
+        while buf.len() < size {
+            let b = buf.len();
+            let chunk = usize::min(size - b, usize::max(self.capacity, b));
+            buf.resize(b + chunk, 0);
+            let r = self.read_source(&mut buf[b..]);
+            match r {
+                Ok(0) => {
+                    buf.truncate(b);
+                    break;
+                }
+                Ok(n) => buf.truncate(b + n),
+                Err(e) => {
+                    buf.truncate(b);
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads from the source, keeping track of its position and verifying
+    /// the data if requested.
+    fn read_source(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        let n = loop {
+            match self.src.read(buf) {
+                Err(ref e) if e.kind() == IoErrorKind::Interrupted => continue,
+                r => break r?,
+            }
+        };
+
+        if let Some(verifier) = &mut self.verifier {
+            if n == 0 {
+                verifier.finish(self.pos, self.len)?;
+            } else {
+                verifier.feed(&buf[..n])?;
+            }
+        }
+
+        if n == 0 {
+            self.len = Some(self.pos);
+        }
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+impl ResettableAccessReader<File> {
+    /// Opens the file at the given path as a resettable data source.
+    pub fn open<P>(path: P) -> IoResult<Self>
+    where
+        P: AsRef<Path>,
+    {
+        File::open(path).map(ResettableAccessReader::new)
+    }
+}
+
+impl<F, R> ResettableAccessReader<Reopen<F, R>>
+where
+    F: FnMut() -> IoResult<R>,
+    R: Read,
+{
+    /// Creates a new resettable reader which obtains a new reader from the
+    /// given function at the beginning of each pass.
+    pub fn from_fn(open: F) -> Self {
+        ResettableAccessReader::new(Reopen::new(open))
+    }
+}
+
+/// Checks that all passes over a source yield the same data, by keeping a
+/// checksum of each block.
+#[derive(Debug, Clone, Default)]
+struct Verifier {
+    /// checksums of all complete blocks seen so far
+    blocks: Vec<u64>,
+    /// checksum of the trailing incomplete block, once the end is known
+    tail: Option<u64>,
+    /// the checksum state of the current block
+    hash: Fnv,
+    /// the number of bytes of the current block seen in this pass
+    filled: usize,
+    /// the number of complete blocks seen in this pass
+    block: usize,
+}
+
+impl Verifier {
+    fn restart(&mut self) {
+        self.hash = Fnv::default();
+        self.filled = 0;
+        self.block = 0;
+    }
+
+    fn feed(&mut self, mut data: &[u8]) -> IoResult<()> {
+        while !data.is_empty() {
+            let l = usize::min(BLOCK_SIZE - self.filled, data.len());
+            self.hash.write(&data[..l]);
+            self.filled += l;
+            data = &data[l..];
+
+            if self.filled == BLOCK_SIZE {
+                let hash = std::mem::take(&mut self.hash).0;
+                self.filled = 0;
+                match self.blocks.get(self.block) {
+                    Some(&h) if h != hash => return Err(changed()),
+                    Some(_) => {}
+                    None => self.blocks.push(hash),
+                }
+                self.block += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, pos: usize, len: Option<usize>) -> IoResult<()> {
+        if len.is_some_and(|len| len != pos) {
+            return Err(changed());
+        }
+        let hash = self.hash.0;
+        match self.tail {
+            Some(h) if h != hash => Err(changed()),
+            Some(_) => Ok(()),
+            None => {
+                self.tail = Some(hash);
+                Ok(())
+            }
+        }
+    }
+}
+
+fn changed() -> IoError {
+    IoError::new(
+        IoErrorKind::InvalidData,
+        "Source yielded different data in another pass",
+    )
+}
+
+/// A 64-bit FNV-1a hash state.
+#[derive(Debug, Clone)]
+struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Fnv {
+    fn write(&mut self, data: &[u8]) {
+        for &b in data {
+            self.0 ^= u64::from(b);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ResettableAccessReader;
+    use std::cell::Cell;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_get() {
+        let data: Vec<u8> = (0..=255).collect();
+
+        let mut read = ResettableAccessReader::with_capacity(Cursor::new(&data), 16);
+
+        assert_eq!(read.get(1).unwrap(), 1);
+        assert_eq!(read.get(200).unwrap(), 200);
+        assert_eq!(read.get(10).unwrap(), 10);
+        assert_eq!(read.get(255).unwrap(), 255);
+        assert_eq!(read.get(0).unwrap(), 0);
+        assert!(read.get(256).is_err());
+        assert!(read.get(usize::MAX).is_err());
+        assert_eq!(read.get(128).unwrap(), 128);
+    }
+
+    #[test]
+    #[allow(clippy::reversed_empty_ranges)]
+    fn test_slice() {
+        let data: Vec<u8> = (0..=255).collect();
+
+        let mut read = ResettableAccessReader::with_capacity(Cursor::new(&data), 16);
+
+        assert_eq!(read.slice(0..0).unwrap(), &[]);
+        assert_eq!(read.slice(1..2).unwrap(), &[1]);
+        assert_eq!(read.slice(100..=150).unwrap(), &data[100..=150]);
+        assert_eq!(read.slice(..=5).unwrap(), &[0, 1, 2, 3, 4, 5]);
+        assert_eq!(read.slice(250..).unwrap(), &[250, 251, 252, 253, 254, 255]);
+        assert_eq!(read.slice(..).unwrap(), &data[..]);
+        assert!(read.slice(200..257).is_err());
+        assert!(read.slice(6..5).is_err());
+        assert!(read.slice(200..=usize::MAX).is_err());
+        assert!(read.slice(usize::MAX..usize::MAX).is_err());
+    }
+
+    #[test]
+    fn reopens_on_backward_access() {
+        let data: Vec<u8> = (0..64).collect();
+        let passes = Cell::new(0);
+
+        let mut read = ResettableAccessReader::with_capacity(
+            super::Reopen::new(|| {
+                passes.set(passes.get() + 1);
+                Ok(&data[..])
+            }),
+            4,
+        );
+
+        assert_eq!(read.get(40).unwrap(), 40);
+        assert_eq!(read.get(42).unwrap(), 42);
+        assert_eq!(passes.get(), 1);
+        assert_eq!(read.get(3).unwrap(), 3);
+        assert_eq!(passes.get(), 2);
+    }
+
+    #[test]
+    fn verification_detects_changes() {
+        let pass = Cell::new(0u8);
+
+        let mut read = ResettableAccessReader::with_verification(super::Reopen::new(|| {
+            pass.set(pass.get() + 1);
+            let mut data = vec![0; 10_000];
+            data[5_000] = pass.get();
+            Ok(Cursor::new(data))
+        }));
+
+        assert_eq!(read.get(9_999).unwrap(), 0);
+        let e = std::io::Error::from(read.get(0).unwrap_err());
+        assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn verification_with_capacity() {
+        let data: Vec<u8> = (0..=255).collect();
+        let pass = Cell::new(0u8);
+
+        let mut read = ResettableAccessReader::with_capacity_and_verification(
+            super::Reopen::new(|| {
+                pass.set(pass.get() + 1);
+                let mut data = data.clone();
+                data[100] = pass.get();
+                Ok(Cursor::new(data))
+            }),
+            16,
+        );
+
+        assert_eq!(read.capacity, 16);
+        assert_eq!(read.slice(200..).unwrap(), &data[200..]);
+        let e = std::io::Error::from(read.slice(..).unwrap_err());
+        assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn open_file() {
+        let data: Vec<u8> = (0..=255).cycle().take(20_000).collect();
+        let path = std::env::temp_dir().join(format!("bra-open-{}", std::process::id()));
+        std::fs::write(&path, &data).unwrap();
+
+        let mut read = ResettableAccessReader::open(&path).unwrap();
+
+        assert_eq!(read.get(15_000).unwrap(), data[15_000]);
+        assert_eq!(read.slice(10..20).unwrap(), &data[10..20]);
+        assert!(read.get(20_000).is_err());
+        assert_eq!(read.get(0).unwrap(), 0);
+
+        drop(read);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,385 @@
+use crate::error::{Error, Result};
+use crate::position::{open_end, resolve_range, seek_target};
+use std::io::{BufRead, IoSlice, Read, Result as IoResult, Seek, SeekFrom};
+use std::ops::RangeBounds;
+
+/// The default size of each segment, in bytes.
+const DEFAULT_SEGMENT_SIZE: usize = 64 * 1024;
+
+/// A buffered reader that greedily retains all memory read into a sequence
+/// of fixed size segments.
+///
+/// It works like [`GreedyAccessReader`], but the retained data is never
+/// moved once fetched: rather than growing a single contiguous buffer, new
+/// segments are allocated as the source is read. This avoids the extra
+/// copies and the peak memory usage of reallocating a large buffer, at the
+/// cost of data not always being contiguous in memory.
+///
+/// A range spanning two segments cannot be obtained with [`slice`], which
+/// fails with [`Error::Discontiguous`], but only with [`slice_segments`], as
+/// one slice per segment.
+///
+/// [`GreedyAccessReader`]: ./struct.GreedyAccessReader.html
+/// [`slice`]: ./struct.SegmentedAccessReader.html#method.slice
+/// [`slice_segments`]: ./struct.SegmentedAccessReader.html#method.slice_segments
+/// [`Error::Discontiguous`]: ./enum.Error.html#variant.Discontiguous
+#[derive(Debug, Clone)]
+pub struct SegmentedAccessReader<R> {
+    inner: R,
+    /// all segments, allocated and zeroed in full, of which only the last
+    /// one may be partially filled
+    segments: Vec<Vec<u8>>,
+    segment_size: usize,
+    /// the total number of bytes retained
+    len: usize,
+    consumed: usize,
+}
+
+impl<R> SegmentedAccessReader<R>
+where
+    R: Read,
+{
+    /// Creates a new segmented buffered reader with the given byte source.
+    pub fn new(src: R) -> Self {
+        SegmentedAccessReader::with_segment_size(src, DEFAULT_SEGMENT_SIZE)
+    }
+
+    /// Creates a new segmented buffered reader with the given byte source
+    /// and the specified segment size.
+    pub fn with_segment_size(src: R, segment_size: usize) -> Self {
+        SegmentedAccessReader {
+            inner: src,
+            segments: Vec::new(),
+            segment_size: usize::max(segment_size, 1),
+            len: 0,
+            consumed: 0,
+        }
+    }
+
+    /// Retrieves the internal reader, discarding the buffer in the process.
+    ///
+    /// Note that any leftover data in the internal buffer is lost.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Retrieves the internal segments in their current state, discarding
+    /// the reader in the process.
+    pub fn into_segments(mut self) -> Vec<Vec<u8>> {
+        // drop the unfilled part of the last segment
+        let full = self.len / self.segment_size;
+        let rest = self.len % self.segment_size;
+        self.segments.truncate(full + (rest > 0) as usize);
+        if rest > 0 {
+            self.segments[full].truncate(rest);
+        }
+        self.segments
+    }
+
+    /// Fetches a single byte from the buffered data source.
+    ///
+    /// # Error
+    ///
+    /// Returns [`Error::OutOfBounds`] if the data source ends before the
+    /// given index, or [`Error::Io`] if reading from the source fails.
+    ///
+    /// [`Error::OutOfBounds`]: ./enum.Error.html#variant.OutOfBounds
+    /// [`Error::Io`]: ./enum.Error.html#variant.Io
+    pub fn get(&mut self, index: usize) -> Result<u8> {
+        let e = Error::range_end(index, 1, self.len)?;
+        self.prefetch_up_to(e)?;
+        if index < self.len {
+            let s = self.segment_size;
+            Ok(self.segments[index / s][index % s])
+        } else {
+            Err(Error::OutOfBounds {
+                range: index..e,
+                available: self.len,
+            })
+        }
+    }
+
+    /// Obtains a slice of bytes.
+    ///
+    /// If the range's end is not bound, the source is read until the end.
+    ///
+    /// # Error
+    ///
+    /// Returns [`Error::OutOfBounds`] if the data source ends before the end
+    /// of the range, [`Error::InvalidRange`] if the range starts after its
+    /// end, [`Error::Discontiguous`] if the range spans more than one
+    /// segment, or [`Error::Io`] if reading from the source fails.
+    ///
+    /// [`Error::OutOfBounds`]: ./enum.Error.html#variant.OutOfBounds
+    /// [`Error::InvalidRange`]: ./enum.Error.html#variant.InvalidRange
+    /// [`Error::Discontiguous`]: ./enum.Error.html#variant.Discontiguous
+    /// [`Error::Io`]: ./enum.Error.html#variant.Io
+    pub fn slice<T>(&mut self, range: T) -> Result<&[u8]>
+    where
+        T: RangeBounds<usize>,
+    {
+        let (b, e) = self.fetch_range(range)?;
+        let s = self.segment_size;
+        if b == e {
+            Ok(&[])
+        } else if b / s == (e - 1) / s {
+            Ok(&self.segments[b / s][b % s..(e - 1) % s + 1])
+        } else {
+            Err(Error::Discontiguous { range: b..e })
+        }
+    }
+
+    /// Obtains the bytes in the given range as a sequence of slices, one for
+    /// each segment spanned by the range, without copying them.
+    ///
+    /// If the range's end is not bound, the source is read until the end.
+    ///
+    /// # Error
+    ///
+    /// Fails under the same conditions as [`slice`], except that the range
+    /// may span several segments.
+    ///
+    /// [`slice`]: ./struct.SegmentedAccessReader.html#method.slice
+    pub fn slice_segments<T>(&mut self, range: T) -> Result<Vec<IoSlice<'_>>>
+    where
+        T: RangeBounds<usize>,
+    {
+        let (b, e) = self.fetch_range(range)?;
+        Ok(segments_in(&self.segments, self.segment_size, b, e)
+            .map(IoSlice::new)
+            .collect())
+    }
+
+    /// Resolves the boundaries of a range, fetching the data up to its end.
+    fn fetch_range<T>(&mut self, range: T) -> Result<(usize, usize)>
+    where
+        T: RangeBounds<usize>,
+    {
+        let (b, e) = resolve_range(&range, 0);
+        let e = match e {
+            Some(e) => e,
+            None => {
+                self.prefetch_to_end()?;
+                open_end(b, self.len)?
+            }
+        };
+
+        self.prefetch_up_to(e)?;
+
+        Error::check_range(b, e, self.len)?;
+        Ok((b, e))
+    }
+
+    /// Reads more data from the source into the last segment, allocating a
+    /// new one if it is full. Returns the number of bytes fetched.
+    fn fetch(&mut self) -> IoResult<usize> {
+        let b = self.len % self.segment_size;
+        if b == 0 && self.segments.len() * self.segment_size == self.len {
+            // each segment is zeroed once, when it is allocated
+            self.segments.push(vec![0; self.segment_size]);
+        }
+        let segment = self.segments.last_mut().unwrap();
+        let o = self.inner.read(&mut segment[b..])?;
+        self.len += o;
+        Ok(o)
+    }
+
+    fn prefetch_up_to(&mut self, i: usize) -> IoResult<()> {
+        while self.len < i {
+            if self.fetch()? == 0 {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn prefetch_to_end(&mut self) -> IoResult<()> {
+        while self.fetch()? > 0 {}
+        Ok(())
+    }
+
+    fn data_to_read(&self) -> &[u8] {
+        if self.consumed >= self.len {
+            return &[];
+        }
+        let s = self.segment_size;
+        let start = self.consumed - self.consumed % s;
+        let end = usize::min(self.len - start, s);
+        &self.segments[self.consumed / s][self.consumed % s..end]
+    }
+}
+
+/// Iterates over the parts of each segment within the given range.
+fn segments_in(
+    segments: &[Vec<u8>],
+    segment_size: usize,
+    b: usize,
+    e: usize,
+) -> impl Iterator<Item = &[u8]> {
+    let first = b / segment_size;
+    let last = if e > b {
+        (e - 1) / segment_size + 1
+    } else {
+        first
+    };
+    segments[first..last]
+        .iter()
+        .enumerate()
+        .map(move |(i, segment)| {
+            let start = (first + i) * segment_size;
+            let from = b.saturating_sub(start);
+            let to = usize::min(e - start, segment.len());
+            &segment[from..to]
+        })
+}
+
+impl<R> Read for SegmentedAccessReader<R>
+where
+    R: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        // we'll be reading from the buffer
+        let mut to_read = self.data_to_read();
+        if to_read.is_empty() {
+            self.fill_buf()?;
+            to_read = self.data_to_read();
+        }
+
+        let len = usize::min(to_read.len(), buf.len());
+        buf[..len].copy_from_slice(&to_read[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl<R> BufRead for SegmentedAccessReader<R>
+where
+    R: Read,
+{
+    fn fill_buf(&mut self) -> IoResult<&[u8]> {
+        if self.data_to_read().is_empty() {
+            self.prefetch_up_to(self.consumed + 1)?;
+        }
+        Ok(self.data_to_read())
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.consumed += amt;
+    }
+}
+
+impl<R> Seek for SegmentedAccessReader<R>
+where
+    R: Read,
+{
+    fn seek(&mut self, pos: SeekFrom) -> IoResult<u64> {
+        let pos = seek_target(pos, self.consumed, || {
+            self.prefetch_to_end()?;
+            Ok(self.len)
+        })?;
+
+        self.prefetch_up_to(pos)?;
+        self.consumed = pos;
+        Ok(pos as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SegmentedAccessReader;
+    use crate::test_util::Chunks;
+    use crate::Error;
+    use std::io::{Read, Seek, SeekFrom};
+
+    #[test]
+    fn smoke_test() {
+        let data: Vec<u8> = (0..=255).collect();
+
+        let mut read = SegmentedAccessReader::with_segment_size(&data[..], 7);
+        let mut o = Vec::new();
+        read.read_to_end(&mut o).unwrap();
+
+        assert_eq!(o, data);
+        let segments = read.into_segments();
+        assert_eq!(segments.len(), 37);
+        assert_eq!(segments[36], &data[252..]);
+    }
+
+    #[test]
+    fn short_reads() {
+        let data: Vec<u8> = (0..=255).collect();
+        let src = Chunks {
+            data: &data,
+            size: 3,
+        };
+        let mut read = SegmentedAccessReader::with_segment_size(src, 16);
+
+        assert_eq!(read.slice(32..48).unwrap(), &data[32..48]);
+        assert_eq!(read.get(100).unwrap(), 100);
+        let joined: Vec<u8> = read
+            .slice_segments(..)
+            .unwrap()
+            .iter()
+            .flat_map(|s| s.iter().cloned())
+            .collect();
+        assert_eq!(joined, data);
+        assert_eq!(read.into_segments().concat(), data);
+    }
+
+    #[test]
+    #[allow(clippy::reversed_empty_ranges)]
+    fn test_slice() {
+        let data: Vec<u8> = (0..=255).collect();
+
+        let mut read = SegmentedAccessReader::with_segment_size(&data[..], 16);
+
+        assert_eq!(read.get(200).unwrap(), 200);
+        assert_eq!(read.slice(0..0).unwrap(), &[]);
+        assert_eq!(read.slice(16..32).unwrap(), &data[16..32]);
+        assert!(matches!(
+            read.slice(10..=40),
+            Err(Error::Discontiguous { range }) if range == (10..41)
+        ));
+        assert_eq!(read.slice(250..).unwrap(), &data[250..]);
+        assert!(read.slice(7..257).is_err());
+        assert!(read.slice(6..5).is_err());
+        assert!(matches!(
+            read.slice(300..),
+            Err(Error::OutOfBounds { available: 256, .. })
+        ));
+        assert!(read.get(256).is_err());
+        assert!(matches!(
+            read.get(usize::MAX),
+            Err(Error::OutOfBounds { available: 256, .. })
+        ));
+    }
+
+    #[test]
+    fn test_slice_segments() {
+        let data: Vec<u8> = (0..=255).collect();
+
+        let mut read = SegmentedAccessReader::with_segment_size(&data[..], 16);
+
+        let segments = read.slice_segments(10..40).unwrap();
+        let lengths: Vec<_> = segments.iter().map(|s| s.len()).collect();
+        assert_eq!(lengths, [6, 16, 8]);
+        let joined: Vec<u8> = segments.iter().flat_map(|s| s.iter().cloned()).collect();
+        assert_eq!(joined, &data[10..40]);
+
+        assert_eq!(read.slice_segments(32..48).unwrap().len(), 1);
+        assert!(read.slice_segments(20..20).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_seek() {
+        let data: Vec<u8> = (0..=255).collect();
+        let mut read = SegmentedAccessReader::with_segment_size(&data[..], 16);
+        let mut chunk = [0; 4];
+
+        assert_eq!(read.seek(SeekFrom::Start(14)).unwrap(), 14);
+        read.read_exact(&mut chunk).unwrap();
+        assert_eq!(chunk, [14, 15, 16, 17]);
+        assert_eq!(read.seek(SeekFrom::End(-2)).unwrap(), 254);
+        assert_eq!(read.read(&mut chunk).unwrap(), 2);
+    }
+}
@@ -0,0 +1,403 @@
+use crate::error::{Error, Result};
+use crate::position::{open_end, resolve_range, seek_target};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::{
+    BufRead, Error as IoError, ErrorKind as IoErrorKind, Read, Result as IoResult, Seek, SeekFrom,
+};
+use std::ops::RangeBounds;
+
+/// The default number of bytes in each page.
+const DEFAULT_PAGE_SIZE: usize = 64 * 1024;
+
+/// The default maximum number of pages kept in memory.
+const DEFAULT_MAX_PAGES: usize = 256;
+
+/// A buffered random access reader for seekable sources, which only fetches
+/// the pages of data that are actually accessed.
+///
+/// It provides the same random access methods as [`GreedyAccessReader`],
+/// but instead of reading and retaining all data up to the requested
+/// position, it seeks straight to the page containing it and keeps it in a
+/// page cache. Once the cache is full, the least recently used page is
+/// evicted, to be fetched again if needed. This makes access to positions
+/// far into files and block devices cheap, in time and memory.
+///
+/// Like the other readers, indices are relative to the position of the
+/// source when it was passed to this construct. The length of the source is
+/// checked again whenever data past its last known end is requested, so
+/// that data appended to a growing file becomes visible. The reader also
+/// implements [`Read`], [`BufRead`] and [`Seek`].
+///
+/// [`GreedyAccessReader`]: ./struct.GreedyAccessReader.html
+/// [`Read`]: https://doc.rust-lang.org/std/io/trait.Read.html
+/// [`BufRead`]: https://doc.rust-lang.org/std/io/trait.BufRead.html
+/// [`Seek`]: https://doc.rust-lang.org/std/io/trait.Seek.html
+#[derive(Debug)]
+pub struct SparseAccessReader<R> {
+    inner: R,
+    page_size: usize,
+    max_pages: usize,
+    /// the cached pages, by page number
+    pages: HashMap<usize, Page>,
+    /// the number of page accesses so far, used for eviction
+    clock: u64,
+    /// the position of the source when it was passed in, and the length of
+    /// the data from there when it was last checked
+    bounds: Option<(u64, usize)>,
+    /// the current position of the source, if known
+    pos: Option<u64>,
+    /// buffer for slices spanning more than one page
+    scratch: Vec<u8>,
+    consumed: usize,
+}
+
+/// A page of data in the cache.
+#[derive(Debug)]
+struct Page {
+    data: Vec<u8>,
+    /// the value of the clock when the page was last accessed
+    last_used: u64,
+}
+
+impl<R> SparseAccessReader<R>
+where
+    R: Read + Seek,
+{
+    /// Creates a new sparse reader with the given byte source, caching up to
+    /// 256 pages of 64 KiB.
+    pub fn new(src: R) -> Self {
+        SparseAccessReader::with_pages(src, DEFAULT_PAGE_SIZE, DEFAULT_MAX_PAGES)
+    }
+
+    /// Creates a new sparse reader with the given byte source, page size
+    /// and maximum number of pages kept in memory.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `page_size` or `max_pages` is zero.
+    pub fn with_pages(src: R, page_size: usize, max_pages: usize) -> Self {
+        assert!(page_size > 0, "page size must not be zero");
+        assert!(max_pages > 0, "maximum number of pages must not be zero");
+        SparseAccessReader {
+            inner: src,
+            page_size,
+            max_pages,
+            pages: HashMap::new(),
+            clock: 0,
+            bounds: None,
+            pos: None,
+            scratch: Vec::new(),
+            consumed: 0,
+        }
+    }
+
+    /// Retrieves the internal reader, discarding the page cache in the
+    /// process.
+    ///
+    /// The position of the reader is unspecified.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Fetches a single byte from the data source.
+    ///
+    /// # Error
+    ///
+    /// Returns [`Error::OutOfBounds`] if the data source ends before the
+    /// given index, or [`Error::Io`] if reading from the source fails.
+    ///
+    /// [`Error::OutOfBounds`]: ./enum.Error.html#variant.OutOfBounds
+    /// [`Error::Io`]: ./enum.Error.html#variant.Io
+    pub fn get(&mut self, index: usize) -> Result<u8> {
+        let e = Error::range_end(index, 1, self.bounds.map_or(0, |(_, len)| len))?;
+        let page_size = self.page_size;
+        let page = self.load_page(index / page_size, index % page_size + 1)?;
+        match page.get(index % page_size) {
+            Some(&v) => Ok(v),
+            None => Err(Error::OutOfBounds {
+                range: index..e,
+                available: self.len()?,
+            }),
+        }
+    }
+
+    /// Obtains a slice of bytes.
+    ///
+    /// If the range spans more than one page, the bytes are copied into a
+    /// separate buffer owned by the reader. If the range's end is not bound,
+    /// the slice contains all remaining data.
+    ///
+    /// # Error
+    ///
+    /// Returns [`Error::OutOfBounds`] if the data source ends before the end
+    /// of the range, [`Error::InvalidRange`] if the range starts after its
+    /// end, or [`Error::Io`] if reading from the source fails.
+    ///
+    /// [`Error::OutOfBounds`]: ./enum.Error.html#variant.OutOfBounds
+    /// [`Error::InvalidRange`]: ./enum.Error.html#variant.InvalidRange
+    /// [`Error::Io`]: ./enum.Error.html#variant.Io
+    pub fn slice<T>(&mut self, range: T) -> Result<&[u8]>
+    where
+        T: RangeBounds<usize>,
+    {
+        let (b, e) = resolve_range(&range, 0);
+        let e = match e {
+            Some(e) => e,
+            None => open_end(b, self.len()?)?,
+        };
+
+        if b > e {
+            return Err(Error::InvalidRange { range: b..e });
+        }
+        if b == e {
+            return Ok(&[]);
+        }
+
+        let first = b / self.page_size;
+        let last = (e - 1) / self.page_size;
+        if first == last {
+            let offset = first * self.page_size;
+            if self.load_page(first, e - offset)?.len() >= e - offset {
+                return Ok(&self.pages[&first].data[b - offset..e - offset]);
+            }
+        } else {
+            let mut scratch = std::mem::take(&mut self.scratch);
+            scratch.clear();
+            for number in first..=last {
+                let offset = number * self.page_size;
+                let needed = usize::min(e - offset, self.page_size);
+                let page = self.load_page(number, needed)?;
+                let from = b.saturating_sub(offset);
+                let to = usize::min(e - offset, page.len());
+                if from < to {
+                    scratch.extend_from_slice(&page[from..to]);
+                }
+            }
+            self.scratch = scratch;
+            if self.scratch.len() == e - b {
+                return Ok(&self.scratch);
+            }
+        }
+
+        Err(Error::OutOfBounds {
+            range: b..e,
+            available: self.len()?,
+        })
+    }
+
+    /// Obtains the total length of the data, seeking to the end of the
+    /// source so that any data appended to it is accounted for.
+    pub fn len(&mut self) -> Result<usize> {
+        Ok(self.refresh_bounds()?.1)
+    }
+
+    /// Checks whether the data source is empty.
+    pub fn is_empty(&mut self) -> Result<bool> {
+        self.len().map(|len| len == 0)
+    }
+
+    /// Determines the starting position of the data, unless already known,
+    /// and its current length.
+    fn refresh_bounds(&mut self) -> IoResult<(u64, usize)> {
+        let start = match self.bounds {
+            Some((start, _)) => start,
+            None => self.inner.stream_position()?,
+        };
+        let end = self.inner.seek(SeekFrom::End(0))?;
+        self.pos = Some(end);
+        let len = end
+            .checked_sub(start)
+            .and_then(|len| usize::try_from(len).ok())
+            .ok_or_else(|| IoError::new(IoErrorKind::InvalidData, "invalid source length"))?;
+        self.bounds = Some((start, len));
+        Ok((start, len))
+    }
+
+    /// Obtains the page with the given number, reading it from the source if
+    /// it is not cached, or if fewer than `needed` bytes of it are cached
+    /// and the source has grown since.
+    fn load_page(&mut self, number: usize, needed: usize) -> IoResult<&[u8]> {
+        self.clock += 1;
+        let cached = self.pages.get(&number).map(|page| page.data.len());
+        if cached.map_or(true, |cached| cached < needed) {
+            let offset = number.saturating_mul(self.page_size);
+            let (start, len) = match self.bounds {
+                Some((start, len)) if len >= offset.saturating_add(needed) => (start, len),
+                // the source may have grown since its length was checked
+                _ => self.refresh_bounds()?,
+            };
+            let size = usize::min(len.saturating_sub(offset), self.page_size);
+            if size <= cached.unwrap_or(0) {
+                // no more data than already cached, if any
+                return Ok(self.touch(number));
+            }
+            let mut data = vec![0; size];
+            let position = start + offset as u64;
+            if self.pos != Some(position) {
+                self.pos = None;
+                self.inner.seek(SeekFrom::Start(position))?;
+            }
+            let r = self.inner.read_exact(&mut data);
+            self.pos = r.as_ref().ok().map(|_| position + size as u64);
+            r?;
+            if cached.is_none() && self.pages.len() >= self.max_pages {
+                self.evict();
+            }
+            self.pages.insert(number, Page { data, last_used: 0 });
+        }
+        Ok(self.touch(number))
+    }
+
+    /// Marks the page with the given number as used, returning its data, or
+    /// an empty slice if it is not cached.
+    fn touch(&mut self, number: usize) -> &[u8] {
+        match self.pages.get_mut(&number) {
+            Some(page) => {
+                page.last_used = self.clock;
+                &page.data
+            }
+            // past the end of the data
+            None => &[],
+        }
+    }
+
+    /// Removes the least recently used page from the cache.
+    fn evict(&mut self) {
+        let oldest = self
+            .pages
+            .iter()
+            .min_by_key(|(_, page)| page.last_used)
+            .map(|(&number, _)| number);
+        if let Some(number) = oldest {
+            self.pages.remove(&number);
+        }
+    }
+}
+
+impl<R> Read for SparseAccessReader<R>
+where
+    R: Read + Seek,
+{
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        let to_read = self.fill_buf()?;
+        let len = usize::min(to_read.len(), buf.len());
+        buf[..len].copy_from_slice(&to_read[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl<R> BufRead for SparseAccessReader<R>
+where
+    R: Read + Seek,
+{
+    fn fill_buf(&mut self) -> IoResult<&[u8]> {
+        let offset = self.consumed % self.page_size;
+        let page = self.load_page(self.consumed / self.page_size, offset + 1)?;
+        Ok(page.get(offset..).unwrap_or(&[]))
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.consumed += amt;
+    }
+}
+
+impl<R> Seek for SparseAccessReader<R>
+where
+    R: Read + Seek,
+{
+    fn seek(&mut self, pos: SeekFrom) -> IoResult<u64> {
+        let pos = seek_target(pos, self.consumed, || Ok(self.len()?))?;
+
+        self.consumed = pos;
+        Ok(pos as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SparseAccessReader;
+    use crate::test_util::{data, Counting};
+    use crate::Error;
+    use std::cell::Cell;
+    use std::io::{Cursor, Read, Seek, SeekFrom};
+
+    #[test]
+    fn test_get_and_slice() {
+        let data = data();
+        let count = Cell::new(0);
+        let src = Counting {
+            inner: Cursor::new(data.clone()),
+            count: &count,
+        };
+        let mut read = SparseAccessReader::with_pages(src, 1024, 4);
+
+        assert_eq!(read.get(99_999).unwrap(), data[99_999]);
+        assert_eq!(read.get(50_000).unwrap(), data[50_000]);
+        assert_eq!(read.slice(50_000..50_100).unwrap(), &data[50_000..50_100]);
+        // only the pages accessed were read
+        assert_eq!(count.get(), 1024 + (100_000 % 1024));
+
+        assert_eq!(read.slice(1000..5000).unwrap(), &data[1000..5000]);
+        assert_eq!(read.slice(99_000..).unwrap(), &data[99_000..]);
+        assert_eq!(read.len().unwrap(), 100_000);
+        assert!(read.pages.len() <= 4);
+        assert!(matches!(
+            read.get(100_000),
+            Err(Error::OutOfBounds {
+                available: 100_000,
+                ..
+            })
+        ));
+        assert!(read.slice(99_000..100_001).is_err());
+        assert!(matches!(
+            read.slice(100_001..),
+            Err(Error::OutOfBounds {
+                available: 100_000,
+                ..
+            })
+        ));
+        assert!(read.get(usize::MAX).is_err());
+    }
+
+    #[test]
+    fn test_read_and_seek() {
+        let data = data();
+        let mut src = Cursor::new(data.clone());
+        src.seek(SeekFrom::Start(10)).unwrap();
+        let mut read = SparseAccessReader::with_pages(src, 1000, 2);
+
+        assert_eq!(read.get(0).unwrap(), data[10]);
+        read.seek(SeekFrom::Start(5_000)).unwrap();
+        let mut chunk = [0; 2_500];
+        read.read_exact(&mut chunk).unwrap();
+        assert_eq!(&chunk[..], &data[5_010..7_510]);
+
+        assert_eq!(read.seek(SeekFrom::End(-10)).unwrap(), 99_980);
+        let mut rest = Vec::new();
+        read.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, &data[99_990..]);
+    }
+
+    #[test]
+    fn growing_source() {
+        let data = data();
+        let src = Cursor::new(data[..1500].to_vec());
+        let mut read = SparseAccessReader::with_pages(src, 1024, 4);
+
+        assert_eq!(read.get(1499).unwrap(), data[1499]);
+        assert!(read.get(1500).is_err());
+
+        read.inner.get_mut().extend_from_slice(&data[1500..3000]);
+        assert_eq!(read.get(1500).unwrap(), data[1500]);
+        assert_eq!(read.slice(1000..2500).unwrap(), &data[1000..2500]);
+        assert_eq!(read.len().unwrap(), 3000);
+
+        read.seek(SeekFrom::Start(2900)).unwrap();
+        read.inner.get_mut().extend_from_slice(&data[3000..3100]);
+        let mut rest = Vec::new();
+        read.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, &data[2900..3100]);
+    }
+}
@@ -0,0 +1,421 @@
+use crate::error::{Error, Result};
+use crate::position::{open_end, resolve_range, seek_target};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, ErrorKind as IoErrorKind, Read, Result as IoResult, Seek, SeekFrom, Write};
+use std::ops::RangeBounds;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The number of bytes paged back from the spill file at a time.
+const PAGE_SIZE: usize = 64 * 1024;
+
+/// The minimum number of bytes requested from the source on each fetch.
+const MIN_FETCH: usize = 16;
+
+/// A buffered reader that greedily retains all data read, moving older data
+/// to a temporary file once a memory threshold is exceeded.
+///
+/// It works like [`GreedyAccessReader`] while the amount of retained data
+/// stays below the threshold. Beyond that point, the oldest data is written
+/// to an anonymous temporary file and paged back on demand, so that random
+/// access over long streams is no longer bounded by the available memory.
+/// The temporary file is removed when the reader is dropped.
+///
+/// [`GreedyAccessReader`]: ./struct.GreedyAccessReader.html
+#[derive(Debug)]
+pub struct SpillingAccessReader<R> {
+    inner: R,
+    /// the data which was not spilled to disk yet, in a buffer whose length
+    /// is the number of bytes ever initialised, so that they are not zeroed
+    /// again
+    buf: Vec<u8>,
+    /// the number of bytes at the start of `buf` which hold fetched data
+    filled: usize,
+    /// the maximum number of bytes to keep in `buf`
+    threshold: usize,
+    /// the spill file, created on the first spill
+    file: Option<SpillFile>,
+    /// the directory where the spill file is created
+    dir: PathBuf,
+    /// the number of bytes in the spill file, which is also the index of the
+    /// first byte in `buf`
+    spilled: usize,
+    /// the last page read back from the spill file
+    page: Vec<u8>,
+    page_start: usize,
+    /// buffer for slices which are not fully in memory
+    scratch: Vec<u8>,
+    consumed: usize,
+}
+
+impl<R> SpillingAccessReader<R>
+where
+    R: Read,
+{
+    /// Creates a new spilling buffered reader with the given byte source,
+    /// keeping at most about `threshold` bytes in memory.
+    ///
+    /// The spill file is created in the system's temporary directory.
+    pub fn new(src: R, threshold: usize) -> Self {
+        SpillingAccessReader::with_spill_dir(src, threshold, std::env::temp_dir())
+    }
+
+    /// Creates a new spilling buffered reader with the given byte source,
+    /// keeping at most about `threshold` bytes in memory and creating the
+    /// spill file in the given directory.
+    pub fn with_spill_dir<P>(src: R, threshold: usize, dir: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        SpillingAccessReader {
+            inner: src,
+            buf: Vec::new(),
+            filled: 0,
+            threshold,
+            file: None,
+            dir: dir.into(),
+            spilled: 0,
+            page: Vec::new(),
+            page_start: 0,
+            scratch: Vec::new(),
+            consumed: 0,
+        }
+    }
+
+    /// Retrieves the internal reader, discarding the buffer and the spill
+    /// file in the process.
+    ///
+    /// Note that any leftover data in the internal buffer is lost.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Obtains the number of bytes currently moved to the spill file.
+    pub fn spilled(&self) -> usize {
+        self.spilled
+    }
+
+    /// Fetches a single byte from the buffered data source.
+    ///
+    /// # Error
+    ///
+    /// Returns [`Error::OutOfBounds`] if the data source ends before the
+    /// given index, or [`Error::Io`] if reading from the source fails.
+    ///
+    /// [`Error::OutOfBounds`]: ./enum.Error.html#variant.OutOfBounds
+    /// [`Error::Io`]: ./enum.Error.html#variant.Io
+    pub fn get(&mut self, index: usize) -> Result<u8> {
+        let e = Error::range_end(index, 1, self.len())?;
+        self.prefetch_up_to(e)?;
+        if index >= self.len() {
+            Err(Error::OutOfBounds {
+                range: index..e,
+                available: self.len(),
+            })
+        } else if index >= self.spilled {
+            Ok(self.buf[index - self.spilled])
+        } else {
+            self.load_page(index)?;
+            Ok(self.page[index - self.page_start])
+        }
+    }
+
+    /// Obtains a slice of bytes.
+    ///
+    /// If the range is not entirely in memory, the bytes are read back from
+    /// the spill file into a separate buffer owned by the reader. If the
+    /// range's end is not bound, the source is read until the end.
+    ///
+    /// # Error
+    ///
+    /// Returns [`Error::OutOfBounds`] if the data source ends before the end
+    /// of the range, [`Error::InvalidRange`] if the range starts after its
+    /// end, or [`Error::Io`] if reading from the source fails.
+    ///
+    /// [`Error::OutOfBounds`]: ./enum.Error.html#variant.OutOfBounds
+    /// [`Error::InvalidRange`]: ./enum.Error.html#variant.InvalidRange
+    /// [`Error::Io`]: ./enum.Error.html#variant.Io
+    pub fn slice<T>(&mut self, range: T) -> Result<&[u8]>
+    where
+        T: RangeBounds<usize>,
+    {
+        let (b, e) = resolve_range(&range, 0);
+        let e = match e {
+            Some(e) => e,
+            None => {
+                self.prefetch_to_end()?;
+                open_end(b, self.len())?
+            }
+        };
+
+        self.prefetch_up_to(e)?;
+
+        Error::check_range(b, e, self.len())?;
+
+        if b >= self.spilled {
+            return Ok(&self.buf[b - self.spilled..e - self.spilled]);
+        }
+        if e <= self.page_start + self.page.len() && b >= self.page_start {
+            return Ok(&self.page[b - self.page_start..e - self.page_start]);
+        }
+
+        let from_file = usize::min(e, self.spilled) - b;
+        self.scratch.resize(from_file, 0);
+        if let Some(file) = &mut self.file {
+            file.read_at(b, &mut self.scratch)?;
+        }
+        if e > self.spilled {
+            self.scratch
+                .extend_from_slice(&self.buf[..e - self.spilled]);
+        }
+        Ok(&self.scratch)
+    }
+
+    /// The total number of bytes retained, in memory and on disk.
+    fn len(&self) -> usize {
+        self.spilled + self.filled
+    }
+
+    /// Reads the page containing the given spilled index from the spill
+    /// file, unless it is already loaded.
+    fn load_page(&mut self, index: usize) -> IoResult<()> {
+        if index >= self.page_start && index < self.page_start + self.page.len() {
+            return Ok(());
+        }
+        let start = index - index % PAGE_SIZE;
+        let end = usize::min(start + PAGE_SIZE, self.spilled);
+        self.page.resize(end - start, 0);
+        self.page_start = start;
+        let r = match &mut self.file {
+            Some(file) => file.read_at(start, &mut self.page),
+            None => Ok(()),
+        };
+        if r.is_err() {
+            self.page.clear();
+        }
+        r
+    }
+
+    /// Moves the oldest half of the in-memory data to the spill file.
+    fn spill(&mut self) -> IoResult<()> {
+        let amount = self.filled - self.threshold / 2;
+        if self.file.is_none() {
+            self.file = Some(SpillFile::create(&self.dir)?);
+        }
+        let file = self.file.as_mut().unwrap();
+        file.write_at(self.spilled, &self.buf[..amount])?;
+        self.buf.copy_within(amount..self.filled, 0);
+        self.filled -= amount;
+        self.spilled += amount;
+        Ok(())
+    }
+
+    /// Reads more data from the source into the buffer, returning the
+    /// number of bytes fetched.
+    fn fetch(&mut self) -> IoResult<usize> {
+        if self.filled > self.threshold {
+            self.spill()?;
+        }
+        let b = self.filled;
+        if self.buf.capacity() - b < MIN_FETCH {
+            self.buf.reserve(usize::max(b, MIN_FETCH));
+        }
+        if self.buf.len() < self.buf.capacity() {
+            // only zero the memory which was never initialised before
+            self.buf.resize(self.buf.capacity(), 0);
+        }
+        let n = self.inner.read(&mut self.buf[b..])?;
+        self.filled += n;
+        Ok(n)
+    }
+
+    fn prefetch_up_to(&mut self, i: usize) -> IoResult<()> {
+        while self.len() < i {
+            if self.fetch()? == 0 {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn prefetch_to_end(&mut self) -> IoResult<()> {
+        while self.fetch()? > 0 {}
+        Ok(())
+    }
+}
+
+impl<R> Read for SpillingAccessReader<R>
+where
+    R: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        let to_read = self.fill_buf()?;
+        let len = usize::min(to_read.len(), buf.len());
+        buf[..len].copy_from_slice(&to_read[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl<R> BufRead for SpillingAccessReader<R>
+where
+    R: Read,
+{
+    fn fill_buf(&mut self) -> IoResult<&[u8]> {
+        if self.consumed >= self.len() {
+            self.prefetch_up_to(self.consumed + 1)?;
+        }
+        if self.consumed >= self.len() {
+            Ok(&[])
+        } else if self.consumed >= self.spilled {
+            Ok(&self.buf[self.consumed - self.spilled..self.filled])
+        } else {
+            self.load_page(self.consumed)?;
+            Ok(&self.page[self.consumed - self.page_start..])
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.consumed += amt;
+    }
+}
+
+impl<R> Seek for SpillingAccessReader<R>
+where
+    R: Read,
+{
+    fn seek(&mut self, pos: SeekFrom) -> IoResult<u64> {
+        let pos = seek_target(pos, self.consumed, || {
+            self.prefetch_to_end()?;
+            Ok(self.len())
+        })?;
+
+        self.prefetch_up_to(pos)?;
+        self.consumed = pos;
+        Ok(pos as u64)
+    }
+}
+
+/// A temporary file which is removed once dropped.
+#[derive(Debug)]
+struct SpillFile {
+    file: File,
+    /// the path to remove on drop, if the file could not be unlinked
+    /// right after creation
+    path: Option<PathBuf>,
+}
+
+impl SpillFile {
+    fn create(dir: &Path) -> IoResult<Self> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or(0);
+        loop {
+            let path = dir.join(format!(
+                ".bra-spill-{}-{}-{}",
+                std::process::id(),
+                COUNTER.fetch_add(1, Ordering::Relaxed),
+                nanos
+            ));
+            match OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create_new(true)
+                .open(&path)
+            {
+                Ok(file) => {
+                    // on platforms which allow it, the file becomes
+                    // anonymous once unlinked
+                    let path = if cfg!(unix) && std::fs::remove_file(&path).is_ok() {
+                        None
+                    } else {
+                        Some(path)
+                    };
+                    return Ok(SpillFile { file, path });
+                }
+                Err(ref e) if e.kind() == IoErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn read_at(&mut self, offset: usize, buf: &mut [u8]) -> IoResult<()> {
+        self.file.seek(SeekFrom::Start(offset as u64))?;
+        self.file.read_exact(buf)
+    }
+
+    fn write_at(&mut self, offset: usize, buf: &[u8]) -> IoResult<()> {
+        self.file.seek(SeekFrom::Start(offset as u64))?;
+        self.file.write_all(buf)
+    }
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        if let Some(path) = &self.path {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SpillingAccessReader;
+    use crate::test_util::data;
+    use crate::Error;
+    use std::io::{Read, Seek, SeekFrom};
+
+    #[test]
+    fn smoke_test() {
+        let data = data();
+
+        let mut read = SpillingAccessReader::new(&data[..], 1_000);
+        let mut o = Vec::new();
+        read.read_to_end(&mut o).unwrap();
+
+        assert_eq!(o, data);
+        assert!(read.spilled() > 90_000);
+    }
+
+    #[test]
+    fn test_get_and_slice() {
+        let data = data();
+
+        let mut read = SpillingAccessReader::new(&data[..], 1_000);
+
+        assert_eq!(read.get(99_999).unwrap(), data[99_999]);
+        assert!(read.spilled() > 0);
+        assert_eq!(read.get(5).unwrap(), data[5]);
+        assert_eq!(read.get(70_000).unwrap(), data[70_000]);
+        assert_eq!(read.slice(10..20).unwrap(), &data[10..20]);
+        assert_eq!(read.slice(65_000..66_000).unwrap(), &data[65_000..66_000]);
+        assert_eq!(read.slice(98_000..).unwrap(), &data[98_000..]);
+        assert!(read.get(100_000).is_err());
+        assert!(read.get(usize::MAX).is_err());
+        assert!(read.slice(10..100_001).is_err());
+        assert!(matches!(
+            read.slice(100_001..),
+            Err(Error::OutOfBounds {
+                available: 100_000,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_read_after_spill() {
+        let data = data();
+
+        let mut read = SpillingAccessReader::new(&data[..], 256);
+
+        assert_eq!(read.seek(SeekFrom::End(0)).unwrap(), 100_000);
+        read.seek(SeekFrom::Start(1_000)).unwrap();
+        let mut chunk = vec![0; 50_000];
+        read.read_exact(&mut chunk).unwrap();
+        assert_eq!(chunk, &data[1_000..51_000]);
+    }
+}
@@ -0,0 +1,432 @@
+//! Append-only byte storage which never moves its contents, and the reading
+//! logic shared by the readers built on it.
+
+use crate::error::{Error, Result};
+use crate::position::{open_end, resolve_range};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{Error as IoError, ErrorKind as IoErrorKind, Read, Result as IoResult};
+use std::ops::RangeBounds;
+use std::ptr;
+use std::slice;
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+/// The size of the first chunk. Each following chunk is twice as large as
+/// the previous one.
+const FIRST_CHUNK_SIZE: usize = 4 * 1024;
+
+/// The maximum number of chunks, enough to fill the address space.
+const MAX_CHUNKS: usize = usize::BITS as usize - 12;
+
+/// An append-only byte buffer whose contents are never moved in memory, so
+/// that slices of it remain valid while more data is appended.
+///
+/// The data is kept in chunks of geometrically increasing size, which are
+/// never reallocated. Data spanning more than one chunk can only be obtained
+/// as one slice per chunk.
+///
+/// The buffer can be read from any number of threads, but it must only be
+/// written by one of them at a time, which the caller has to ensure.
+pub(crate) struct StableBuffer {
+    chunks: [AtomicPtr<u8>; MAX_CHUNKS],
+    /// the number of bytes written and published to readers
+    len: AtomicUsize,
+}
+
+impl StableBuffer {
+    pub(crate) fn new() -> Self {
+        StableBuffer {
+            chunks: std::array::from_fn(|_| AtomicPtr::new(ptr::null_mut())),
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    /// The number of bytes available for reading.
+    pub(crate) fn len(&self) -> usize {
+        self.len.load(Ordering::Acquire)
+    }
+
+    /// Obtains the byte at the given index, if available.
+    pub(crate) fn get(&self, index: usize) -> Option<u8> {
+        if index >= self.len() {
+            return None;
+        }
+        let (chunk, offset) = locate(index);
+        // SAFETY: the byte was published, so its chunk is allocated and it
+        // is no longer written to
+        unsafe { Some(*self.chunks[chunk].load(Ordering::Acquire).add(offset)) }
+    }
+
+    /// Obtains the bytes from `b` to `e`, if available and held in a single
+    /// chunk.
+    pub(crate) fn slice(&self, b: usize, e: usize) -> Option<&[u8]> {
+        if b > e || e > self.len() {
+            return None;
+        }
+        if b == e {
+            return Some(&[]);
+        }
+        let (first, offset) = locate(b);
+        let (last, _) = locate(e - 1);
+        if first != last {
+            return None;
+        }
+        // SAFETY: the bytes were published, so their chunk is allocated,
+        // they are no longer written to, and the chunk lives as long as the
+        // buffer
+        unsafe {
+            let ptr = self.chunks[first].load(Ordering::Acquire).add(offset);
+            Some(slice::from_raw_parts(ptr, e - b))
+        }
+    }
+
+    /// Obtains the bytes from `b` to `e` as one slice for each chunk
+    /// spanned, if available.
+    pub(crate) fn segments(&self, b: usize, e: usize) -> Option<Vec<&[u8]>> {
+        if b > e || e > self.len() {
+            return None;
+        }
+        let mut segments = Vec::new();
+        let mut i = b;
+        while i < e {
+            let (chunk, offset) = locate(i);
+            let n = usize::min(chunk_size(chunk) - offset, e - i);
+            // SAFETY: as above, for each chunk spanned by the range
+            unsafe {
+                let ptr = self.chunks[chunk].load(Ordering::Acquire).add(offset);
+                segments.push(slice::from_raw_parts(ptr, n));
+            }
+            i += n;
+        }
+        Some(segments)
+    }
+
+    /// Obtains the unpublished space after the last byte written, up to the
+    /// end of its chunk, allocating the chunk if needed.
+    ///
+    /// # Safety
+    ///
+    /// The caller must be the only one writing to the buffer until the space
+    /// is published with [`commit`] or no longer used.
+    ///
+    /// [`commit`]: #method.commit
+    #[allow(clippy::mut_from_ref)]
+    pub(crate) unsafe fn spare(&self) -> &mut [u8] {
+        let len = self.len.load(Ordering::Relaxed);
+        let (chunk, offset) = locate(len);
+        let mut ptr = self.chunks[chunk].load(Ordering::Acquire);
+        if ptr.is_null() {
+            let memory = vec![0u8; chunk_size(chunk)].into_boxed_slice();
+            ptr = Box::into_raw(memory) as *mut u8;
+            self.chunks[chunk].store(ptr, Ordering::Release);
+        }
+        slice::from_raw_parts_mut(ptr.add(offset), chunk_size(chunk) - offset)
+    }
+
+    /// Publishes the first `amount` bytes of the space last obtained with
+    /// [`spare`] to readers.
+    ///
+    /// # Safety
+    ///
+    /// The caller must be the only one writing to the buffer, and `amount`
+    /// must not exceed the length of the space obtained.
+    ///
+    /// [`spare`]: #method.spare
+    pub(crate) unsafe fn commit(&self, amount: usize) {
+        let len = self.len.load(Ordering::Relaxed);
+        self.len.store(len + amount, Ordering::Release);
+    }
+}
+
+impl Drop for StableBuffer {
+    fn drop(&mut self) {
+        for (i, chunk) in self.chunks.iter_mut().enumerate() {
+            let ptr = *chunk.get_mut();
+            if !ptr.is_null() {
+                // SAFETY: the chunk was allocated in `spare` as a boxed slice
+                // of this size, and is no longer borrowed
+                unsafe {
+                    drop(Box::from_raw(ptr::slice_from_raw_parts_mut(
+                        ptr,
+                        chunk_size(i),
+                    )));
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Debug for StableBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StableBuffer")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+/// Exclusive access to the data source of a [`StableReader`].
+///
+/// # Safety
+///
+/// `with_source` must not run `f` while another call to it, on this or any
+/// other thread, is running its own closure.
+///
+/// [`StableReader`]: ./struct.StableReader.html
+pub(crate) unsafe trait SourceLock {
+    type Source: Read;
+
+    /// Runs `f` with exclusive access to the source.
+    fn with_source<T>(&self, f: impl FnOnce(&mut Self::Source) -> T) -> T;
+
+    /// Retrieves the source.
+    fn into_source(self) -> Self::Source;
+}
+
+// SAFETY: the mutex is held while running `f`
+unsafe impl<R: Read> SourceLock for Mutex<R> {
+    type Source = R;
+
+    fn with_source<T>(&self, f: impl FnOnce(&mut R) -> T) -> T {
+        f(&mut self.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    fn into_source(self) -> R {
+        self.into_inner().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+// SAFETY: the cell is borrowed mutably while running `f`, and it cannot be
+// shared between threads
+unsafe impl<R: Read> SourceLock for RefCell<R> {
+    type Source = R;
+
+    fn with_source<T>(&self, f: impl FnOnce(&mut R) -> T) -> T {
+        f(&mut self.borrow_mut())
+    }
+
+    fn into_source(self) -> R {
+        self.into_inner()
+    }
+}
+
+/// A greedy reader which fetches from a locked source into a
+/// [`StableBuffer`], so that all data can be accessed through a shared
+/// reference.
+///
+/// [`StableBuffer`]: ./struct.StableBuffer.html
+#[derive(Debug)]
+pub(crate) struct StableReader<L> {
+    source: L,
+    buf: StableBuffer,
+}
+
+impl<L> StableReader<L>
+where
+    L: SourceLock,
+{
+    pub(crate) fn new(source: L) -> Self {
+        StableReader {
+            source,
+            buf: StableBuffer::new(),
+        }
+    }
+
+    pub(crate) fn into_source(self) -> L::Source {
+        self.source.into_source()
+    }
+
+    pub(crate) fn get(&self, index: usize) -> Result<u8> {
+        if let Some(v) = self.buf.get(index) {
+            Ok(v)
+        } else {
+            let e = Error::range_end(index, 1, self.buf.len())?;
+            self.prefetch_up_to(e)?;
+
+            self.buf.get(index).ok_or(Error::OutOfBounds {
+                range: index..e,
+                available: self.buf.len(),
+            })
+        }
+    }
+
+    pub(crate) fn slice<T>(&self, range: T) -> Result<&[u8]>
+    where
+        T: RangeBounds<usize>,
+    {
+        let (b, e) = self.fetch_range(range)?;
+        self.buf
+            .slice(b, e)
+            .ok_or(Error::Discontiguous { range: b..e })
+    }
+
+    pub(crate) fn slice_cow<T>(&self, range: T) -> Result<Cow<'_, [u8]>>
+    where
+        T: RangeBounds<usize>,
+    {
+        let (b, e) = self.fetch_range(range)?;
+        Ok(match self.buf.slice(b, e) {
+            Some(data) => Cow::Borrowed(data),
+            None => Cow::Owned(self.buf.segments(b, e).unwrap_or_default().concat()),
+        })
+    }
+
+    pub(crate) fn slice_segments<T>(&self, range: T) -> Result<Vec<&[u8]>>
+    where
+        T: RangeBounds<usize>,
+    {
+        let (b, e) = self.fetch_range(range)?;
+        Ok(self.buf.segments(b, e).unwrap_or_default())
+    }
+
+    pub(crate) fn len(&self) -> Result<usize> {
+        self.prefetch_up_to(usize::MAX)?;
+        Ok(self.buf.len())
+    }
+
+    pub(crate) fn is_empty(&self) -> Result<bool> {
+        self.prefetch_up_to(1)?;
+        Ok(self.buf.len() == 0)
+    }
+
+    /// Resolves the boundaries of a range, fetching the data up to its end.
+    fn fetch_range<T>(&self, range: T) -> Result<(usize, usize)>
+    where
+        T: RangeBounds<usize>,
+    {
+        let (b, e) = resolve_range(&range, 0);
+        let e = match e {
+            Some(e) => e,
+            None => open_end(b, self.len()?)?,
+        };
+
+        self.prefetch_up_to(e)?;
+
+        Error::check_range(b, e, self.buf.len())?;
+        Ok((b, e))
+    }
+
+    /// Reads from the source until at least `end` bytes are available, or
+    /// the source ends.
+    fn prefetch_up_to(&self, end: usize) -> IoResult<()> {
+        if self.buf.len() >= end {
+            return Ok(());
+        }
+
+        self.source.with_source(|src| {
+            // another thread may have fetched the data while waiting for the
+            // lock
+            while self.buf.len() < end {
+                // SAFETY: the buffer is only written to with exclusive
+                // access to the source
+                let spare = unsafe { self.buf.spare() };
+                let room = spare.len();
+                match src.read(spare) {
+                    Ok(0) => break,
+                    // a safe `Read` implementation may claim more bytes than
+                    // it was given room for
+                    Ok(n) if n > room => {
+                        return Err(IoError::new(
+                            IoErrorKind::InvalidData,
+                            "source reported reading more bytes than requested",
+                        ))
+                    }
+                    // SAFETY: as above, and `n` is within the space obtained,
+                    // which was zeroed when allocated
+                    Ok(n) => unsafe { self.buf.commit(n) },
+                    Err(ref e) if e.kind() == IoErrorKind::Interrupted => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+/// The size of the chunk with the given index.
+fn chunk_size(chunk: usize) -> usize {
+    FIRST_CHUNK_SIZE << chunk
+}
+
+/// Determines the chunk holding the given index, and the offset of the
+/// index within the chunk.
+fn locate(index: usize) -> (usize, usize) {
+    let n = index / FIRST_CHUNK_SIZE + 1;
+    let chunk = (usize::BITS - 1 - n.leading_zeros()) as usize;
+    let start = FIRST_CHUNK_SIZE * ((1 << chunk) - 1);
+    (chunk, index - start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{locate, StableBuffer, StableReader, FIRST_CHUNK_SIZE};
+    use crate::Error;
+    use std::cell::RefCell;
+    use std::io::{ErrorKind, Read, Result};
+
+    #[test]
+    fn test_locate() {
+        assert_eq!(locate(0), (0, 0));
+        assert_eq!(locate(FIRST_CHUNK_SIZE - 1), (0, FIRST_CHUNK_SIZE - 1));
+        assert_eq!(locate(FIRST_CHUNK_SIZE), (1, 0));
+        assert_eq!(
+            locate(3 * FIRST_CHUNK_SIZE - 1),
+            (1, 2 * FIRST_CHUNK_SIZE - 1)
+        );
+        assert_eq!(locate(3 * FIRST_CHUNK_SIZE), (2, 0));
+    }
+
+    #[test]
+    fn slices_remain_valid() {
+        let buf = StableBuffer::new();
+        let data: Vec<u8> = (0..20_000u32).map(|i| i as u8).collect();
+
+        let mut written = 0;
+        let mut first = None;
+        while written < data.len() {
+            unsafe {
+                let spare = buf.spare();
+                let n = spare.len().min(1000).min(data.len() - written);
+                spare[..n].copy_from_slice(&data[written..written + n]);
+                buf.commit(n);
+                written += n;
+            }
+            if first.is_none() {
+                first = buf.slice(0, 1000);
+            }
+        }
+
+        assert_eq!(first, Some(&data[..1000]));
+        assert_eq!(buf.slice(4200, 4400), Some(&data[4200..4400]));
+        assert_eq!(buf.slice(4000, 4200), None);
+        let segments = buf.segments(100, 19_000).unwrap();
+        assert_eq!(segments.len(), 3);
+        assert_eq!(segments.concat(), &data[100..19_000]);
+        assert_eq!(buf.get(12_345), Some(data[12_345]));
+        assert_eq!(buf.segments(100, 20_001), None);
+    }
+
+    /// A reader which claims to have read more bytes than it was given.
+    struct Lying;
+
+    impl Read for Lying {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            Ok(buf.len() + 100)
+        }
+    }
+
+    #[test]
+    fn lying_source() {
+        let reader = StableReader::new(RefCell::new(Lying));
+        // the claimed bytes are never published, let alone read
+        assert!(matches!(
+            reader.get(FIRST_CHUNK_SIZE + 54),
+            Err(Error::Io(ref e)) if e.kind() == ErrorKind::InvalidData
+        ));
+        assert!(matches!(
+            reader.len(),
+            Err(Error::Io(ref e)) if e.kind() == ErrorKind::InvalidData
+        ));
+    }
+}
@@ -0,0 +1,158 @@
+use crate::error::Result;
+use crate::stable::StableReader;
+use std::io::Read;
+use std::ops::RangeBounds;
+use std::sync::Mutex;
+
+/// A buffered random access reader which can be shared between threads.
+///
+/// Unlike [`GreedyAccessReader`], data is accessed through a shared
+/// reference, so that the reader can be placed behind an `Arc` (or borrowed
+/// by scoped threads) and used by several threads at once. Only one thread
+/// at a time reads from the underlying source, under a lock, while bytes
+/// which were already fetched are read without any locking.
+///
+/// Fetched data never moves in memory, so slices obtained from the reader
+/// remain valid for as long as the reader itself. It is kept in chunks which
+/// double in size, starting at 4 KiB, so at most half of the memory held is
+/// unused. Data is never copied: a range spanning two chunks cannot be
+/// obtained with [`slice`], which fails with [`Error::Discontiguous`], but
+/// only with [`slice_segments`], as one slice per chunk. Since chunks double
+/// in size, only a few dozen offsets in the whole stream are chunk
+/// boundaries.
+///
+/// [`GreedyAccessReader`]: ./struct.GreedyAccessReader.html
+/// [`slice`]: ./struct.SyncAccessReader.html#method.slice
+/// [`slice_segments`]: ./struct.SyncAccessReader.html#method.slice_segments
+/// [`Error::Discontiguous`]: ./enum.Error.html#variant.Discontiguous
+#[derive(Debug)]
+pub struct SyncAccessReader<R> {
+    inner: StableReader<Mutex<R>>,
+}
+
+impl<R> SyncAccessReader<R>
+where
+    R: Read,
+{
+    /// Creates a new reader which can be shared between threads.
+    pub fn new(src: R) -> Self {
+        SyncAccessReader {
+            inner: StableReader::new(Mutex::new(src)),
+        }
+    }
+
+    /// Drops the buffered data and returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner.into_source()
+    }
+
+    /// Fetches a single byte from the buffered data source.
+    pub fn get(&self, index: usize) -> Result<u8> {
+        self.inner.get(index)
+    }
+
+    /// Obtains a slice of bytes.
+    ///
+    /// If the range's end is not bound (e.g. `5..`), the source is read
+    /// until the end, and the slice contains all remaining data.
+    ///
+    /// # Error
+    ///
+    /// Returns [`Error::OutOfBounds`] if the data source ends before the end
+    /// of the range, [`Error::InvalidRange`] if the range starts after its
+    /// end, [`Error::Discontiguous`] if the range spans more than one chunk,
+    /// or [`Error::Io`] if reading from the source fails.
+    ///
+    /// [`Error::OutOfBounds`]: ./enum.Error.html#variant.OutOfBounds
+    /// [`Error::InvalidRange`]: ./enum.Error.html#variant.InvalidRange
+    /// [`Error::Discontiguous`]: ./enum.Error.html#variant.Discontiguous
+    /// [`Error::Io`]: ./enum.Error.html#variant.Io
+    pub fn slice<T>(&self, range: T) -> Result<&[u8]>
+    where
+        T: RangeBounds<usize>,
+    {
+        self.inner.slice(range)
+    }
+
+    /// Obtains the bytes in the given range as a sequence of slices, one for
+    /// each chunk spanned by the range, without copying them.
+    ///
+    /// If the range's end is not bound, the source is read until the end.
+    ///
+    /// # Error
+    ///
+    /// Fails under the same conditions as [`slice`], except that the range
+    /// may span several chunks.
+    ///
+    /// [`slice`]: ./struct.SyncAccessReader.html#method.slice
+    pub fn slice_segments<T>(&self, range: T) -> Result<Vec<&[u8]>>
+    where
+        T: RangeBounds<usize>,
+    {
+        self.inner.slice_segments(range)
+    }
+
+    /// Reads the source until the end, returning the total length of the
+    /// data.
+    pub fn len(&self) -> Result<usize> {
+        self.inner.len()
+    }
+
+    /// Checks whether the data source is empty, fetching at most a few bytes
+    /// from it.
+    pub fn is_empty(&self) -> Result<bool> {
+        self.inner.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SyncAccessReader;
+    use crate::Error;
+    use std::thread;
+
+    #[test]
+    fn smoke_test() {
+        let data: Vec<u8> = (0..=255).collect();
+        let reader = SyncAccessReader::new(&data[..]);
+
+        assert_eq!(reader.get(10).unwrap(), 10);
+        let head = reader.slice(..4).unwrap();
+        assert_eq!(
+            reader.slice(250..).unwrap(),
+            &[250, 251, 252, 253, 254, 255]
+        );
+        assert_eq!(head, &[0, 1, 2, 3]);
+        assert_eq!(reader.len().unwrap(), 256);
+        assert!(matches!(
+            reader.get(256),
+            Err(Error::OutOfBounds { available: 256, .. })
+        ));
+    }
+
+    #[test]
+    fn shared_between_threads() {
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        let reader = SyncAccessReader::new(&data[..]);
+
+        thread::scope(|s| {
+            for t in 0..4 {
+                let (reader, data) = (&reader, &data);
+                s.spawn(move || {
+                    for i in (t * 1000..data.len()).step_by(3331) {
+                        let e = usize::min(i + 5000, data.len());
+                        let segments = reader.slice_segments(i..e).unwrap();
+                        assert_eq!(segments.concat(), &data[i..e]);
+                        assert_eq!(reader.get(i).unwrap(), data[i]);
+                    }
+                });
+            }
+        });
+
+        assert_eq!(reader.slice_segments(..).unwrap().concat(), data);
+        assert!(matches!(
+            reader.slice(4000..5000),
+            Err(Error::Discontiguous { .. })
+        ));
+    }
+}
@@ -0,0 +1,43 @@
+//! Helpers shared by the tests of the different readers.
+
+use std::cell::Cell;
+use std::io::{Read, Result, Seek, SeekFrom};
+
+/// Test data large enough to span many buffers or pages.
+pub(crate) fn data() -> Vec<u8> {
+    (0..100_000u32).map(|i| (i % 251) as u8).collect()
+}
+
+/// A reader which counts the number of bytes read from it.
+pub(crate) struct Counting<'a, R> {
+    pub(crate) inner: R,
+    pub(crate) count: &'a Cell<usize>,
+}
+
+impl<R: Read> Read for Counting<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count.set(self.count.get() + n);
+        Ok(n)
+    }
+}
+
+impl<R: Seek> Seek for Counting<'_, R> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+/// A reader which yields at most `size` bytes per read call.
+#[derive(Clone)]
+pub(crate) struct Chunks<'a> {
+    pub(crate) data: &'a [u8],
+    pub(crate) size: usize,
+}
+
+impl Read for Chunks<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let len = usize::min(buf.len(), self.size);
+        self.data.read(&mut buf[..len])
+    }
+}
@@ -0,0 +1,363 @@
+use crate::error::{Error, Result};
+use crate::position::{open_end, resolve_range, seek_target};
+use std::io::{self, BufRead, Read, Result as IoResult, Seek, SeekFrom};
+use std::ops::RangeBounds;
+
+/// The minimum number of bytes requested from the source on each fetch.
+const MIN_FETCH: usize = 16;
+
+/// A buffered reader with random access to a bounded window of past data.
+///
+/// Like [`GreedyAccessReader`], it retains the data read from the source so
+/// that it can be accessed again at an arbitrary position. However, only the
+/// last `window` bytes before the reading position are guaranteed to be
+/// kept. Older bytes are evicted automatically as the reader advances, and
+/// accessing them results in an [`Error::Evicted`] error. This keeps memory bounded
+/// when parsing long streams which only need to backtrack a little.
+///
+/// The position indices are always relative to the position of the data
+/// source when it was passed to this construct, and are not affected by
+/// evictions.
+///
+/// [`GreedyAccessReader`]: ./struct.GreedyAccessReader.html
+/// [`Error::Evicted`]: ./enum.Error.html#variant.Evicted
+#[derive(Debug, Clone)]
+pub struct WindowAccessReader<R> {
+    inner: R,
+    /// the buffer, whose length is the number of bytes ever initialised,
+    /// so that they are not zeroed again
+    buf: Vec<u8>,
+    /// the number of bytes at the start of `buf` which hold fetched data
+    filled: usize,
+    /// the index of the first byte in `buf`
+    base: usize,
+    /// the index of the next byte to be read
+    consumed: usize,
+    /// the maximum number of bytes to keep before `consumed`
+    window: usize,
+}
+
+impl<R> WindowAccessReader<R>
+where
+    R: Read,
+{
+    /// Creates a new windowed buffered reader with the given byte source,
+    /// retaining at least `window` bytes behind the reading position.
+    pub fn new(src: R, window: usize) -> Self {
+        WindowAccessReader {
+            inner: src,
+            buf: Vec::new(),
+            filled: 0,
+            base: 0,
+            consumed: 0,
+            window,
+        }
+    }
+
+    /// Retrieves the internal reader, discarding the buffer in the process.
+    ///
+    /// Note that any leftover data in the internal buffer is lost.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Obtains the maximum number of bytes retained behind the reading
+    /// position.
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    /// Obtains the lowest index which can still be accessed.
+    pub fn lowest_index(&self) -> usize {
+        usize::max(self.consumed.saturating_sub(self.window), self.base)
+    }
+
+    /// Fetches a single byte from the buffered data source.
+    ///
+    /// # Error
+    ///
+    /// Returns [`Error::Evicted`] if the byte was already evicted,
+    /// [`Error::OutOfBounds`] if the data source ends before the given
+    /// index, or [`Error::Io`] if reading from the source fails.
+    ///
+    /// [`Error::Evicted`]: ./enum.Error.html#variant.Evicted
+    /// [`Error::OutOfBounds`]: ./enum.Error.html#variant.OutOfBounds
+    /// [`Error::Io`]: ./enum.Error.html#variant.Io
+    pub fn get(&mut self, index: usize) -> Result<u8> {
+        self.check_evicted(index)?;
+        let e = Error::range_end(index, 1, self.end())?;
+        self.prefetch_up_to(e)?;
+
+        self.data()
+            .get(index - self.base)
+            .cloned()
+            .ok_or(Error::OutOfBounds {
+                range: index..e,
+                available: self.end(),
+            })
+    }
+
+    /// Obtains a slice of bytes.
+    ///
+    /// If the range's end is not bound, the source is read until the end.
+    ///
+    /// # Error
+    ///
+    /// Returns [`Error::Evicted`] if part of the range was already evicted,
+    /// [`Error::OutOfBounds`] if the data source ends before the end of the
+    /// range, [`Error::InvalidRange`] if the range starts after its end, or
+    /// [`Error::Io`] if reading from the source fails.
+    ///
+    /// [`Error::Evicted`]: ./enum.Error.html#variant.Evicted
+    /// [`Error::OutOfBounds`]: ./enum.Error.html#variant.OutOfBounds
+    /// [`Error::InvalidRange`]: ./enum.Error.html#variant.InvalidRange
+    /// [`Error::Io`]: ./enum.Error.html#variant.Io
+    pub fn slice<T>(&mut self, range: T) -> Result<&[u8]>
+    where
+        T: RangeBounds<usize>,
+    {
+        let (b, e) = resolve_range(&range, 0);
+        self.check_evicted(b)?;
+
+        let e = match e {
+            Some(e) => e,
+            None => {
+                self.prefetch_to_end()?;
+                open_end(b, self.end())?
+            }
+        };
+
+        self.prefetch_up_to(e)?;
+
+        Error::check_range(b, e, self.end())?;
+        Ok(&self.data()[b - self.base..e - self.base])
+    }
+
+    fn check_evicted(&self, index: usize) -> Result<()> {
+        let lowest = self.lowest_index();
+        if index < lowest {
+            Err(Error::Evicted { index, lowest })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Drops the bytes which fell out of the window. The buffer is only
+    /// compacted once there are at least as many bytes to drop as bytes to
+    /// keep, so that the cost of moving the data is amortized.
+    fn evict(&mut self) {
+        let dead = usize::min(self.lowest_index() - self.base, self.filled);
+        if dead > 0 && dead >= self.filled - dead {
+            self.buf.copy_within(dead..self.filled, 0);
+            self.filled -= dead;
+            self.base += dead;
+        }
+    }
+
+    /// Reads more data from the source into the buffer, returning the
+    /// number of bytes fetched.
+    fn fetch(&mut self) -> IoResult<usize> {
+        let b = self.filled;
+        if self.buf.capacity() - b < MIN_FETCH {
+            self.buf.reserve(usize::max(b, MIN_FETCH));
+        }
+        if self.buf.len() < self.buf.capacity() {
+            // only zero the memory which was never initialised before
+            self.buf.resize(self.buf.capacity(), 0);
+        }
+        let n = self.inner.read(&mut self.buf[b..])?;
+        self.filled += n;
+        Ok(n)
+    }
+
+    fn prefetch_up_to(&mut self, i: usize) -> IoResult<()> {
+        while self.end() < i {
+            if self.fetch()? == 0 {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn prefetch_to_end(&mut self) -> IoResult<()> {
+        while self.fetch()? > 0 {}
+        Ok(())
+    }
+
+    /// Fetches the data up to the given index ahead of moving the reading
+    /// position there. Bytes which would fall out of the window right away
+    /// are read from the source without being retained.
+    fn skip_to(&mut self, i: usize) -> IoResult<()> {
+        let end = self.end();
+        let keep = i.saturating_sub(self.window);
+        if keep > end {
+            let mut source = (&mut self.inner).take((keep - end) as u64);
+            let skipped = io::copy(&mut source, &mut io::sink())?;
+            self.filled = 0;
+            self.base = end + skipped as usize;
+        }
+        self.prefetch_up_to(i)
+    }
+
+    /// The index right after the last byte fetched.
+    fn end(&self) -> usize {
+        self.base + self.filled
+    }
+
+    fn data(&self) -> &[u8] {
+        &self.buf[..self.filled]
+    }
+
+    fn data_to_read(&self) -> &[u8] {
+        self.data().get(self.consumed - self.base..).unwrap_or(&[])
+    }
+}
+
+impl<R> Read for WindowAccessReader<R>
+where
+    R: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        // we'll be reading from the buffer
+        let mut to_read = self.data_to_read();
+        if to_read.is_empty() {
+            self.fill_buf()?;
+            to_read = self.data_to_read();
+        }
+
+        let len = usize::min(to_read.len(), buf.len());
+        buf[..len].copy_from_slice(&to_read[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl<R> BufRead for WindowAccessReader<R>
+where
+    R: Read,
+{
+    fn fill_buf(&mut self) -> IoResult<&[u8]> {
+        if self.data_to_read().is_empty() {
+            self.prefetch_up_to(self.consumed + 1)?;
+        }
+        Ok(self.data_to_read())
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.consumed += amt;
+        self.evict();
+    }
+}
+
+impl<R> Seek for WindowAccessReader<R>
+where
+    R: Read,
+{
+    fn seek(&mut self, pos: SeekFrom) -> IoResult<u64> {
+        let pos = seek_target(pos, self.consumed, || {
+            self.prefetch_to_end()?;
+            Ok(self.end())
+        })?;
+
+        self.check_evicted(pos)?;
+        self.skip_to(pos)?;
+        self.consumed = pos;
+        self.evict();
+        Ok(pos as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WindowAccessReader;
+    use crate::Error;
+    use std::io::{Read, Seek, SeekFrom};
+
+    #[test]
+    fn smoke_test() {
+        let data: Vec<u8> = (0..=255).collect();
+
+        let mut read = WindowAccessReader::new(&data[..], 4);
+        let mut o = Vec::new();
+        read.read_to_end(&mut o).unwrap();
+
+        assert_eq!(o, data);
+    }
+
+    #[test]
+    fn test_eviction() {
+        let data: Vec<u8> = (0..=255).collect();
+        let mut read = WindowAccessReader::new(&data[..], 8);
+
+        assert_eq!(read.get(100).unwrap(), 100);
+        assert_eq!(read.slice(4..8).unwrap(), &[4, 5, 6, 7]);
+
+        let mut chunk = [0; 20];
+        read.read_exact(&mut chunk).unwrap();
+        assert_eq!(read.lowest_index(), 12);
+        assert_eq!(read.get(12).unwrap(), 12);
+        assert_eq!(read.slice(12..24).unwrap(), &data[12..24]);
+
+        match read.get(11) {
+            Err(Error::Evicted { index, lowest }) => {
+                assert_eq!(index, 11);
+                assert_eq!(lowest, 12);
+            }
+            r => panic!("unexpected result {:?}", r),
+        }
+        assert!(read.slice(4..16).is_err());
+
+        // reading far ahead does not evict data within the window
+        read.read_exact(&mut [0; 200]).unwrap();
+        assert_eq!(read.get(212).unwrap(), 212);
+        assert!(read.get(211).is_err());
+        assert_eq!(read.slice(250..).unwrap(), &[250, 251, 252, 253, 254, 255]);
+        assert!(matches!(
+            read.slice(300..),
+            Err(Error::OutOfBounds { available: 256, .. })
+        ));
+        assert!(read.get(256).is_err());
+        assert!(matches!(
+            read.get(usize::MAX),
+            Err(Error::OutOfBounds { .. })
+        ));
+    }
+
+    #[test]
+    fn test_seek() {
+        let data: Vec<u8> = (0..=255).collect();
+        let mut read = WindowAccessReader::new(&data[..], 8);
+        let mut chunk = [0; 4];
+
+        assert_eq!(read.seek(SeekFrom::Start(100)).unwrap(), 100);
+        assert_eq!(read.seek(SeekFrom::Current(-8)).unwrap(), 92);
+        read.read_exact(&mut chunk).unwrap();
+        assert_eq!(chunk, [92, 93, 94, 95]);
+        assert!(read.seek(SeekFrom::Current(-16)).is_err());
+        assert_eq!(read.seek(SeekFrom::End(-4)).unwrap(), 252);
+        read.read_exact(&mut chunk).unwrap();
+        assert_eq!(chunk, [252, 253, 254, 255]);
+    }
+
+    #[test]
+    fn seek_forward_keeps_memory_bounded() {
+        let data: Vec<u8> = (0..1_000_000u32).map(|i| (i % 251) as u8).collect();
+        let mut read = WindowAccessReader::new(&data[..], 8);
+
+        read.get(4).unwrap();
+        assert_eq!(read.seek(SeekFrom::Start(900_000)).unwrap(), 900_000);
+        // the skipped data was never buffered
+        assert!(read.buf.capacity() < 1024);
+        assert_eq!(read.lowest_index(), 899_992);
+        assert_eq!(read.get(899_992).unwrap(), data[899_992]);
+        assert!(matches!(read.get(899_991), Err(Error::Evicted { .. })));
+        let mut chunk = [0; 4];
+        read.read_exact(&mut chunk).unwrap();
+        assert_eq!(chunk, &data[900_000..900_004]);
+
+        // seeking past the end of the data
+        assert_eq!(read.seek(SeekFrom::Start(2_000_000)).unwrap(), 2_000_000);
+        assert_eq!(read.read(&mut chunk).unwrap(), 0);
+        assert!(read.get(999_999).is_err());
+    }
+}